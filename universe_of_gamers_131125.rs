@@ -1,19 +1,43 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::{
-    program::{invoke},
+    hash::hashv,
+    program::{invoke, invoke_signed},
     system_instruction,
     instruction::Instruction,
+    sysvar,
+    ed25519_program,
 };
-use anchor_spl::token::{
-    self, Token, TokenAccount, Mint, Transfer, MintTo, SetAuthority,
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_2022;
+use anchor_spl::token_interface::{
+    self, TokenInterface, TokenAccount, Mint, TransferChecked, MintTo, SetAuthority,
 };
-use anchor_spl::token::spl_token::instruction::AuthorityType;
+use spl_token_2022::extension::{
+    group_member_pointer::GroupMemberPointer, metadata_pointer::MetadataPointer,
+    transfer_fee::{TransferFeeAmount, TransferFeeConfig}, BaseStateWithExtensions, StateWithExtensions,
+};
+use spl_token_group_interface::state::TokenGroupMember;
+use spl_token_metadata_interface::state::TokenMetadata;
+use spl_token_2022::instruction::AuthorityType;
+use spl_token_2022::instruction as token_instruction;
 use spl_token::native_mint;
-use spl_token::instruction as token_instruction;
+use mpl_token_metadata::accounts::Metadata as MplMetadata;
+use mpl_token_metadata::instructions::{
+    CreateMetadataAccountV3CpiBuilder, DelegateTransferV1CpiBuilder, RevokeTransferV1CpiBuilder,
+    TransferV1CpiBuilder, VerifyCollectionV1CpiBuilder,
+};
+use mpl_token_metadata::types::{DataV2, TokenStandard};
+use mpl_bubblegum::instructions::{
+    CreateTreeConfigCpiBuilder, DelegateCpiBuilder, MintToCollectionV1CpiBuilder, TransferCpiBuilder,
+};
+use mpl_bubblegum::types::{
+    Collection, Creator, MetadataArgs, TokenProgramVersion, TokenStandard as CnftTokenStandard,
+};
+use mpl_bubblegum::utils::get_asset_id;
 
 declare_id!("uogw4oywo9nb4gyX6euzQgTHSkLLuiLc1FCEz4fpFHC");
 
-pub fn pay_fee<'info>(use_sol: bool, payer: &AccountInfo<'info>, treasury_pda: &AccountInfo<'info>, payer_ata: Option<&AccountInfo<'info>>, treasury_ata: Option<&AccountInfo<'info>>, token_program: &AccountInfo<'info>, system_program: &AccountInfo<'info>, amount: u64) -> Result<()> {
+pub fn pay_fee<'info>(use_sol: bool, payer: &AccountInfo<'info>, treasury_pda: &AccountInfo<'info>, payer_ata: Option<&AccountInfo<'info>>, treasury_ata: Option<&AccountInfo<'info>>, mint: &InterfaceAccount<'info, Mint>, token_program: &AccountInfo<'info>, system_program: &AccountInfo<'info>, amount: u64) -> Result<()> {
     if amount == 0 {
         return Ok(());
     }
@@ -34,521 +58,8724 @@ pub fn pay_fee<'info>(use_sol: bool, payer: &AccountInfo<'info>, treasury_pda: &
         let payer_ata = payer_ata.ok_or(MarketplaceError::InvalidOwner)?;
         let treasury_ata = treasury_ata.ok_or(MarketplaceError::InvalidOwner)?;
 
-        token::transfer(
+        token_interface::transfer_checked(
             CpiContext::new(
                 token_program.clone(),
-                token::Transfer {
+                TransferChecked {
                     from: payer_ata.clone(),
+                    mint: mint.to_account_info(),
                     to: treasury_ata.clone(),
                     authority: payer.clone(),
                 },
             ),
             amount,
+            mint.decimals,
         )?;
     }
     Ok(())
 }
 
-#[program]
-pub mod universe_of_gamers {
-    use super::*;
-
-    pub fn initialize_market(ctx: Context<InitializeMarket>, mint_fee_bps: u16, trade_fee_bps: u16, relist_fee_bps: u16, multisig_admins: Vec<Pubkey>, multisig_threshold: u8) -> Result<()> {
-        require!(multisig_threshold > 0, MarketplaceError::InvalidThreshold);
-        require!(
-            multisig_threshold as usize <= multisig_admins.len() + 1,
-            MarketplaceError::InvalidThreshold
-        );
+/// Amount withheld by a Token-2022 transfer-fee mint when moving `amount`.
+/// Legacy SPL mints and Token-2022 mints without the extension withhold nothing.
+pub fn transfer_fee_for<'info>(mint: &InterfaceAccount<'info, Mint>, amount: u64) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner != token_2022::ID {
+        return Ok(0);
+    }
 
-        let cfg = &mut ctx.accounts.market_config;
-        cfg.admin = ctx.accounts.admin.key();
-        cfg.mint_fee_bps = mint_fee_bps;
-        cfg.trade_fee_bps = trade_fee_bps;
-        cfg.relist_fee_bps = relist_fee_bps;
-        cfg.treasury_bump = *ctx.bumps.get("treasury_pda").unwrap();
-        cfg.multisig_admins = multisig_admins;
-        cfg.multisig_threshold = multisig_threshold;
+    let data = mint_info.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    match state.get_extension::<TransferFeeConfig>() {
+        Ok(cfg) => cfg
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or(MarketplaceError::MathOverflow.into()),
+        Err(_) => Ok(0),
+    }
+}
 
-        Ok(())
+/// Amount to send so that `net_amount` arrives after a Token-2022 transfer
+/// fee. Fee legs use it so the treasury is not short-changed by the mint.
+pub fn gross_up_transfer_fee<'info>(mint: &InterfaceAccount<'info, Mint>, net_amount: u64) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner != token_2022::ID || net_amount == 0 {
+        return Ok(net_amount);
     }
 
-    pub fn initialize_treasury(_ctx: Context<InitializeTreasury>) -> Result<()> {
-        Ok(())
+    let data = mint_info.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    match state.get_extension::<TransferFeeConfig>() {
+        Ok(cfg) => {
+            let fee = cfg
+                .calculate_inverse_epoch_fee(Clock::get()?.epoch, net_amount)
+                .ok_or(MarketplaceError::MathOverflow)?;
+            net_amount
+                .checked_add(fee)
+                .ok_or(MarketplaceError::MathOverflow.into())
+        }
+        Err(_) => Ok(net_amount),
     }
+}
 
-    pub fn mint_and_list(ctx: Context<MintAndList>, price: u64, use_sol: bool, mint_fee_spl: u64, _name: String, _symbol: String, _uri: String, _seller_fee_basis_points: u16) -> Result<()> {
-        token::mint_to(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                MintTo {
-                    mint: ctx.accounts.mint.to_account_info(),
-                    to: ctx.accounts.seller_nft_ata.to_account_info(),
-                    authority: ctx.accounts.mint_authority.to_account_info(),
-                },
-                &[&[
-                    b"mint_auth",
-                    ctx.accounts.mint.key().as_ref(),
-                    &[*ctx.bumps.get("mint_authority").unwrap()],
-                ]],
-            ),
-            1,
-        )?;
+/// Tops up `mint` so it stays rent-exempt once the token-metadata interface
+/// writes `name`, `symbol` and `uri` into it; `initialize` reallocs the mint
+/// but does not fund the extra space.
+pub fn fund_embedded_metadata_rent<'info>(payer: &AccountInfo<'info>, mint: &AccountInfo<'info>, system_program: &AccountInfo<'info>, name: &str, symbol: &str, uri: &str) -> Result<()> {
+    let metadata = TokenMetadata {
+        mint: mint.key(),
+        name: name.to_string(),
+        symbol: symbol.to_string(),
+        uri: uri.to_string(),
+        ..Default::default()
+    };
+    fund_mint_growth(payer, mint, system_program, metadata.tlv_size_of()?)
+}
 
-        token::set_authority(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                SetAuthority {
-                    current_authority: ctx.accounts.mint_authority.to_account_info(),
-                    account_or_mint: ctx.accounts.mint.to_account_info(),
-                },
-                &[&[
-                    b"mint_auth",
-                    ctx.accounts.mint.key().as_ref(),
-                    &[*ctx.bumps.get("mint_authority").unwrap()],
-                ]],
-            ),
-            AuthorityType::MintTokens,
-            None,
+/// Funds rent for `extra` bytes a Token-2022 instruction is about to add to
+/// `mint` without paying for them itself.
+pub fn fund_mint_growth<'info>(payer: &AccountInfo<'info>, mint: &AccountInfo<'info>, system_program: &AccountInfo<'info>, extra: usize) -> Result<()> {
+    let space = mint
+        .data_len()
+        .checked_add(extra)
+        .ok_or(MarketplaceError::MathOverflow)?;
+
+    let rent_due = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(mint.lamports());
+    if rent_due > 0 {
+        invoke(
+            &system_instruction::transfer(&payer.key(), &mint.key(), rent_due),
+            &[payer.clone(), mint.clone(), system_program.clone()],
         )?;
+    }
+    Ok(())
+}
 
-        token::approve(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                token::Approve {
-                    to: ctx.accounts.seller_nft_ata.to_account_info(),
-                    delegate: ctx.accounts.escrow_signer.to_account_info(),
-                    authority: ctx.accounts.seller.to_account_info(),
-                },
-            ),
-            1,
-        )?;
+/// True when a Token-2022 mint carries a group member pointer to itself, so
+/// its collection is recorded as an embedded `TokenGroupMember`.
+pub fn has_embedded_group_member<'info>(mint: &InterfaceAccount<'info, Mint>) -> Result<bool> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner != token_2022::ID {
+        return Ok(false);
+    }
 
-        let mint_fee_bps = ctx.accounts.market_config.mint_fee_bps as u64;
-        let expected_fee = price
-            .saturating_mul(mint_fee_bps)
-            .checked_div(10_000)
-            .ok_or(MarketplaceError::MathOverflow)?;
+    let data = mint_info.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    Ok(match state.get_extension::<GroupMemberPointer>() {
+        Ok(pointer) => Option::<Pubkey>::from(pointer.member_address) == Some(mint.key()),
+        Err(_) => false,
+    })
+}
 
-        if use_sol {
-            anchor_lang::solana_program::program::invoke(
-                &system_instruction::transfer(
-                    &ctx.accounts.seller.key(),
-                    &ctx.accounts.treasury_pda.key(),
-                    price,
-                ),
-                &[
-                    ctx.accounts.seller.to_account_info(),
-                    ctx.accounts.treasury_pda.to_account_info(),
-                    ctx.accounts.system_program.to_account_info(),
-                ],
-            )?;
+/// True when a Token-2022 mint carries a metadata pointer to itself, i.e. its
+/// name/symbol/uri live in the mint account via the token-metadata interface.
+pub fn has_embedded_metadata<'info>(mint: &InterfaceAccount<'info, Mint>) -> Result<bool> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner != token_2022::ID {
+        return Ok(false);
+    }
 
-            anchor_lang::solana_program::program::invoke(
-                &system_instruction::transfer(
-                    &ctx.accounts.seller.key(),
-                    &ctx.accounts.admin.key(),
-                    expected_fee,
-                ),
-                &[
-                    ctx.accounts.seller.to_account_info(),
-                    ctx.accounts.admin.to_account_info(),
-                    ctx.accounts.system_program.to_account_info(),
-                ],
-            )?;
-        } else {
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    token::Transfer {
-                        from: ctx.accounts.seller_payment_ata.to_account_info(),
-                        to: ctx.accounts.treasury_token_account.to_account_info(),
-                        authority: ctx.accounts.seller.to_account_info(),
-                    },
-                ),
-                price,
-            )?;
+    let data = mint_info.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    Ok(match state.get_extension::<MetadataPointer>() {
+        Ok(pointer) => Option::<Pubkey>::from(pointer.metadata_address) == Some(mint.key()),
+        Err(_) => false,
+    })
+}
 
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    token::Transfer {
-                        from: ctx.accounts.seller_payment_ata.to_account_info(),
-                        to: ctx.accounts.admin_token_account.to_account_info(),
-                        authority: ctx.accounts.seller.to_account_info(),
-                    },
-                ),
-                mint_fee_spl,
-            )?;
+/// Verified collection of an NFT: the Token-2022 group of a group-member mint,
+/// or the verified Metaplex collection recorded in its metadata account.
+pub fn verified_collection<'info>(mint_info: &AccountInfo<'info>, metadata: Option<&AccountInfo<'info>>) -> Result<Option<Pubkey>> {
+    if *mint_info.owner == token_2022::ID {
+        let data = mint_info.try_borrow_data()?;
+        let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+        let points_to_self = match state.get_extension::<GroupMemberPointer>() {
+            Ok(pointer) => Option::<Pubkey>::from(pointer.member_address) == Some(mint_info.key()),
+            Err(_) => false,
+        };
+        if points_to_self {
+            if let Ok(member) = state.get_extension::<TokenGroupMember>() {
+                return Ok(Some(member.group));
+            }
         }
+    }
 
-        let listing = &mut ctx.accounts.listing;
-        listing.seller = ctx.accounts.seller.key();
-        listing.nft_mint = ctx.accounts.mint.key();
-        listing.price = price;
-        listing.use_sol = use_sol;
-        listing.bump = *ctx.bumps.get("listing").unwrap();
+    let metadata = match metadata {
+        Some(metadata) => metadata,
+        None => return Ok(None),
+    };
+    let (expected, _) = MplMetadata::find_pda(&mint_info.key());
+    require_keys_eq!(metadata.key(), expected, MarketplaceError::InvalidNFT);
+
+    let data = metadata.try_borrow_data()?;
+    let metadata = MplMetadata::safe_deserialize(&data)
+        .map_err(|_| MarketplaceError::InvalidNFT)?;
+    Ok(metadata
+        .collection
+        .filter(|collection| collection.verified)
+        .map(|collection| collection.key))
+}
 
-        Ok(())
+pub fn count_multisig_signers(cfg: &MarketConfig, signers: &[Pubkey]) -> u8 {
+    let mut counted: Vec<Pubkey> = Vec::with_capacity(signers.len());
+    for key in signers {
+        if cfg.multisig_admins.contains(key) && !counted.contains(key) {
+            counted.push(*key);
+        }
     }
+    counted.len() as u8
+}
 
-    pub fn buy_nft(ctx: Context<BuyNft>) -> Result<()> {
-        let listing = &ctx.accounts.listing;
+/// Requires at least `multisig_threshold` distinct configured admins among
+/// `signers`.
+pub fn require_multisig(cfg: &MarketConfig, signers: &[Pubkey]) -> Result<()> {
+    require!(
+        count_multisig_signers(cfg, signers) >= cfg.multisig_threshold,
+        MarketplaceError::Unauthorized
+    );
+    Ok(())
+}
 
-        require_keys_eq!(
-            listing.seller,
-            ctx.accounts.seller.key(),
-            MarketplaceError::InvalidOwner
-        );
+pub fn discounted_fee_bps<'info>(base_bps: u16, payer: Pubkey, fee_tier: Option<&Account<'info, FeeTier>>, fee_tier_holding: Option<&InterfaceAccount<'info, TokenAccount>>, fee_tier_stake: Option<&Account<'info, UogStake>>) -> Result<u16> {
+    let tier = match fee_tier {
+        Some(tier) => tier,
+        None => return Ok(base_bps),
+    };
 
-        let trade_fee = listing
-            .price
-            .saturating_mul(ctx.accounts.market_config.trade_fee_bps as u64)
-            / 10_000;
-        let seller_amount = listing.price.saturating_sub(trade_fee);
+    match tier.kind {
+        FeeTierKind::Wallet => {
+            require_keys_eq!(tier.subject, payer, MarketplaceError::InvalidFeeTier);
+        }
+        FeeTierKind::Holding => {
+            let holding = fee_tier_holding.ok_or(MarketplaceError::InvalidFeeTier)?;
+            require_keys_eq!(holding.owner, payer, MarketplaceError::InvalidFeeTier);
+            require_keys_eq!(holding.mint, tier.subject, MarketplaceError::InvalidFeeTier);
+            require!(holding.amount >= tier.min_balance, MarketplaceError::InvalidFeeTier);
+        }
+        FeeTierKind::Staked => {
+            let stake = fee_tier_stake.ok_or(MarketplaceError::InvalidFeeTier)?;
+            require_keys_eq!(stake.owner, payer, MarketplaceError::InvalidFeeTier);
+            require_keys_eq!(stake.uog_mint, tier.subject, MarketplaceError::InvalidFeeTier);
+            require!(stake.amount >= tier.min_balance, MarketplaceError::InvalidFeeTier);
+        }
+    }
 
-        let buyer_payment_info = ctx.accounts.buyer_payment_ata.to_account_info();
-        let seller_payment_info = ctx.accounts.seller_payment_ata.to_account_info();
-        let _treasury_token_info = ctx.accounts.treasury_token_account.to_account_info();
-        let token_program_info = ctx.accounts.token_program.to_account_info();
-        let _system_program_info = ctx.accounts.system_program.to_account_info();
+    let discounted = (base_bps as u64)
+        .saturating_mul(10_000u64.saturating_sub(tier.discount_bps as u64))
+        / 10_000;
+    Ok(discounted as u16)
+}
 
-        let escrow_signer_bump = *ctx.bumps.get("escrow_signer").unwrap_or(&listing.bump);
+pub fn proof_accounts<'a, 'info>(remaining_accounts: &'a [AccountInfo<'info>]) -> Vec<(&'a AccountInfo<'info>, bool, bool)> {
+    remaining_accounts
+        .iter()
+        .map(|acc| (acc, false, false))
+        .collect()
+}
 
-        if ctx.accounts.buyer_payment_ata.key() == ctx.accounts.buyer.key() {
-            anchor_lang::solana_program::program::invoke(
-                &system_instruction::transfer(
-                    &ctx.accounts.buyer.key(),
-                    &ctx.accounts.seller.key(),
-                    seller_amount,
-                ),
-                &[
-                    ctx.accounts.buyer.to_account_info(),
-                    ctx.accounts.seller.to_account_info(),
-                    ctx.accounts.system_program.to_account_info(),
-                ],
-            )?;
+/// Moves `amount` out of a program-owned vault token account signed by the
+/// vault authority PDA, then closes the vault to `rent_destination`.
+/// Transfer fees withheld on the vault are harvested to the mint first, as
+/// Token-2022 refuses to close an account still holding them; `mint` must
+/// then be writable.
+#[allow(clippy::too_many_arguments)]
+pub fn release_from_vault<'info>(token_program: &AccountInfo<'info>, mint: &AccountInfo<'info>, vault: &AccountInfo<'info>, destination: &AccountInfo<'info>, authority: &AccountInfo<'info>, rent_destination: &AccountInfo<'info>, signer_seeds: &[&[&[u8]]], amount: u64) -> Result<()> {
+    let decimals = InterfaceAccount::<Mint>::try_from(mint)?.decimals;
 
-            anchor_lang::solana_program::program::invoke(
-                &system_instruction::transfer(
-                    &ctx.accounts.buyer.key(),
-                    &ctx.accounts.treasury_pda.key(),
-                    trade_fee,
-                ),
-                &[
-                    ctx.accounts.buyer.to_account_info(),
-                    ctx.accounts.treasury_pda.to_account_info(),
-                    ctx.accounts.system_program.to_account_info(),
-                ],
-            )?;
-        } else {
-            token::transfer(
-                CpiContext::new(
-                    token_program_info.clone(),
-                    token::Transfer {
-                        from: buyer_payment_info.clone(),
-                        to: seller_payment_info.clone(),
-                        authority: ctx.accounts.buyer.to_account_info(),
-                    },
-                ),
-                seller_amount,
-            )?;
+    if withheld_transfer_fees(vault)? > 0 {
+        invoke(
+            &spl_token_2022::extension::transfer_fee::instruction::harvest_withheld_tokens_to_mint(
+                &token_2022::ID,
+                mint.key,
+                &[vault.key],
+            )?,
+            &[mint.clone(), vault.clone()],
+        )?;
+    }
 
-            token::transfer(
-                CpiContext::new(
-                    token_program_info.clone(),
-                    token::Transfer {
-                        from: buyer_payment_info.clone(),
-                        to: _treasury_token_info.clone(),
-                        authority: ctx.accounts.buyer.to_account_info(),
-                    },
-                ),
-                trade_fee,
-            )?;
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            TransferChecked {
+                from: vault.clone(),
+                mint: mint.clone(),
+                to: destination.clone(),
+                authority: authority.clone(),
+            },
+            signer_seeds,
+        ),
+        amount,
+        decimals,
+    )?;
+
+    token_interface::close_account(CpiContext::new_with_signer(
+        token_program.clone(),
+        token_interface::CloseAccount {
+            account: vault.clone(),
+            destination: rent_destination.clone(),
+            authority: authority.clone(),
+        },
+        signer_seeds,
+    ))?;
+
+    Ok(())
+}
+
+/// Token-2022 transfer fees withheld on `token_account`, zero for accounts
+/// without the transfer fee extension.
+pub fn withheld_transfer_fees(token_account: &AccountInfo) -> Result<u64> {
+    if *token_account.owner != token_2022::ID {
+        return Ok(0);
+    }
+
+    let data = token_account.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+    Ok(state
+        .get_extension::<TransferFeeAmount>()
+        .map(|fees| u64::from(fees.withheld_amount))
+        .unwrap_or(0))
+}
+
+/// Rarity recorded in a Token-2022 mint's embedded metadata under the
+/// `rarity` key, if any. Metadata whose update authority is not
+/// `trusted_authority` is ignored, since its holder could write any rarity.
+pub fn rarity_from_metadata<'info>(mint: &InterfaceAccount<'info, Mint>, trusted_authority: &Pubkey) -> Result<Option<Rarity>> {
+    if !has_embedded_metadata(mint)? {
+        return Ok(None);
+    }
+
+    let mint_info = mint.to_account_info();
+    let data = mint_info.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    let metadata = match state.get_variable_len_extension::<TokenMetadata>() {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(None),
+    };
+    if Option::<Pubkey>::from(metadata.update_authority) != Some(*trusted_authority) {
+        return Ok(None);
+    }
+
+    Ok(metadata
+        .additional_metadata
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("rarity"))
+        .and_then(|(_, value)| Rarity::parse(value)))
+}
+
+/// Picks a rarity from `weights` (indexed by `Rarity`) using `seed`.
+pub fn roll_rarity(weights: &[u16; 4], seed: u64) -> Rarity {
+    let total: u64 = weights.iter().map(|w| *w as u64).sum();
+    let mut roll = seed % total.max(1);
+    for (i, weight) in weights.iter().enumerate() {
+        if roll < *weight as u64 {
+            return [Rarity::Common, Rarity::Rare, Rarity::Epic, Rarity::Legendary][i];
         }
+        roll -= *weight as u64;
+    }
+    Rarity::Common
+}
 
-        let signer_seeds: &[&[u8]] = &[
-            b"escrow_signer",
-            listing.nft_mint.as_ref(),
-            &[escrow_signer_bump],
-        ];
+pub struct PityPull {
+    pub rarity: Rarity,
+    /// The guaranteed-Epic rule upgraded the roll
+    pub pity_hit: bool,
+    /// Legendary odds were boosted for this roll
+    pub rate_up: bool,
+}
+
+/// Rolls one pull against `weights` under the pack's pity `rules` and
+/// advances the player's counters.
+pub fn roll_with_pity(rules: &PityRules, weights: &[u16; 4], counter: &mut PityCounter, roll: u64) -> PityPull {
+    let mut weights = *weights;
+    let rate_up = rules.legendary_rate_up_after > 0
+        && counter.pulls_since_legendary >= rules.legendary_rate_up_after;
+    if rate_up {
+        let boosted = (weights[Rarity::Legendary as usize] as u64)
+            .saturating_mul(rules.legendary_rate_up_bps as u64)
+            / 10_000;
+        weights[Rarity::Legendary as usize] = boosted.min(u16::MAX as u64) as u16;
+    }
 
-        token::transfer(
+    let mut rarity = roll_rarity(&weights, roll);
+    let pity_hit = rules.guaranteed_epic_within > 0
+        && counter.pulls_since_epic.saturating_add(1) >= rules.guaranteed_epic_within
+        && rarity < Rarity::Epic;
+    if pity_hit {
+        rarity = Rarity::Epic;
+    }
+
+    counter.total_pulls += 1;
+    counter.pulls_since_epic = if rarity >= Rarity::Epic {
+        0
+    } else {
+        counter.pulls_since_epic.saturating_add(1)
+    };
+    counter.pulls_since_legendary = if rarity == Rarity::Legendary {
+        0
+    } else {
+        counter.pulls_since_legendary.saturating_add(1)
+    };
+
+    PityPull { rarity, pity_hit, rate_up }
+}
+
+/// Hash recorded in the SlotHashes sysvar for the first slot after
+/// `after_slot`. Fails once that slot has aged out of the sysvar window.
+pub fn slot_hash_after(slot_hashes: &AccountInfo, after_slot: u64) -> Result<[u8; 32]> {
+    let data = slot_hashes.try_borrow_data()?;
+    let len = u64::from_le_bytes(data[..8].try_into().unwrap()) as usize;
+    const ENTRY: usize = 8 + 32;
+
+    // Entries are ordered newest first; the window must still reach back to
+    // `after_slot` so the chosen hash cannot drift as the window moves.
+    let oldest = 8 + (len.saturating_sub(1)) * ENTRY;
+    require!(
+        len > 0 && u64::from_le_bytes(data[oldest..oldest + 8].try_into().unwrap()) <= after_slot,
+        MarketplaceError::RevealExpired
+    );
+
+    let mut found = None;
+    for i in 0..len {
+        let offset = 8 + i * ENTRY;
+        let slot = u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+        if slot <= after_slot {
+            break;
+        }
+        found = Some(<[u8; 32]>::try_from(&data[offset + 8..offset + ENTRY]).unwrap());
+    }
+    found.ok_or(MarketplaceError::PackNotReady.into())
+}
+
+/// Requires that the instruction right before the current one is an Ed25519
+/// program verification of `message` signed by `signer`.
+pub fn verify_ed25519_signature(instructions_sysvar: &AccountInfo, signer: &Pubkey, message: &[u8]) -> Result<()> {
+    let current = sysvar::instructions::load_current_index_checked(instructions_sysvar)?;
+    require!(current > 0, MarketplaceError::InvalidSignature);
+    let ix = sysvar::instructions::load_instruction_at_checked(current as usize - 1, instructions_sysvar)?;
+    require_keys_eq!(ix.program_id, ed25519_program::ID, MarketplaceError::InvalidSignature);
+
+    // [num_signatures, padding, then one 14-byte offsets block per signature]
+    let data = &ix.data;
+    require!(data.len() >= 16 && data[0] == 1, MarketplaceError::InvalidSignature);
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let (pubkey_offset, message_offset, message_size) =
+        (read_u16(6) as usize, read_u16(10) as usize, read_u16(12) as usize);
+    require!(
+        [read_u16(4), read_u16(8), read_u16(14)].iter().all(|i| *i == u16::MAX),
+        MarketplaceError::InvalidSignature
+    );
+    require!(
+        data.len() >= pubkey_offset + 32 && data.len() >= message_offset + message_size,
+        MarketplaceError::InvalidSignature
+    );
+
+    require!(
+        &data[pubkey_offset..pubkey_offset + 32] == signer.as_ref()
+            && &data[message_offset..message_offset + message_size] == message,
+        MarketplaceError::InvalidSignature
+    );
+    Ok(())
+}
+
+/// Pays `amount` out of a program-owned escrow PDA: lamports straight off the
+/// PDA for SOL, or from `vault` (owned by the PDA) for SPL tokens.
+#[allow(clippy::too_many_arguments)]
+pub fn pay_from_escrow<'info>(use_sol: bool, escrow: &AccountInfo<'info>, recipient: &AccountInfo<'info>, vault: Option<&AccountInfo<'info>>, mint: &InterfaceAccount<'info, Mint>, token_program: &AccountInfo<'info>, signer_seeds: &[&[&[u8]]], amount: u64) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    if use_sol {
+        **escrow.try_borrow_mut_lamports()? -= amount;
+        **recipient.try_borrow_mut_lamports()? += amount;
+    } else {
+        let vault = vault.ok_or(MarketplaceError::MissingEscrowVault)?;
+        token_interface::transfer_checked(
             CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.seller_nft_ata.to_account_info(),
-                    to: ctx.accounts.buyer_nft_ata.to_account_info(),
-                    authority: ctx.accounts.escrow_signer.to_account_info(),
+                token_program.clone(),
+                TransferChecked {
+                    from: vault.clone(),
+                    mint: mint.to_account_info(),
+                    to: recipient.clone(),
+                    authority: escrow.clone(),
                 },
-                &[signer_seeds],
+                signer_seeds,
             ),
-            1,
+            amount,
+            mint.decimals,
         )?;
+    }
+    Ok(())
+}
 
-        Ok(())
+/// Deterministic 3v3 battle engine. Given both teams and a seed it always
+/// produces the same outcome, so the program can re-run a disputed battle.
+pub mod battle {
+    use super::*;
+
+    pub const TEAM_SIZE: usize = 3;
+    pub const MAX_ROUNDS: u32 = 50;
+    pub const SKILL_MULTIPLIER_BPS: u64 = 15_000;
+    pub const ULTIMATE_MULTIPLIER_BPS: u64 = 12_000;
+    pub const CRIT_MULTIPLIER_BPS: u64 = 15_000;
+
+    #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct HeroStats {
+        pub hp: u32,
+        pub atk: u32,
+        pub def: u32,
+        pub spd: u32,
+        pub crit_bps: u16,
+        /// Turns between single-target skills; 0 means no skill
+        pub skill_cooldown: u8,
+        /// Turns between team-wide ultimates; 0 means no ultimate
+        pub ultimate_cooldown: u8,
     }
 
-    pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>, amount: u64) -> Result<()> {
-        let cfg = &ctx.accounts.market_config;
-        let multisig_admins = &cfg.multisig_admins;
+    impl HeroStats {
+        /// Battle stats of a hero of `rarity`. Disputes only trust stats
+        /// derived from the on-chain rarity registry.
+        pub fn for_rarity(rarity: Rarity) -> Self {
+            let (hp, atk, def, spd, crit_bps, skill_cooldown, ultimate_cooldown) = match rarity {
+                Rarity::Common => (1_000, 100, 50, 100, 500, 3, 0),
+                Rarity::Rare => (1_200, 120, 60, 105, 700, 3, 5),
+                Rarity::Epic => (1_450, 145, 70, 110, 1_000, 3, 5),
+                Rarity::Legendary => (1_750, 175, 85, 120, 1_500, 2, 4),
+            };
+            HeroStats {
+                hp,
+                atk,
+                def,
+                spd,
+                crit_bps,
+                skill_cooldown,
+                ultimate_cooldown,
+            }
+        }
+    }
+
+    pub type Team = [HeroStats; TEAM_SIZE];
+
+    pub fn team_for(rarities: &[Rarity; TEAM_SIZE]) -> Team {
+        rarities.map(HeroStats::for_rarity)
+    }
+
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum Outcome {
+        TeamA,
+        TeamB,
+        Draw,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    enum Action {
+        Attack,
+        Skill,
+        Ultimate,
+    }
 
-        let mut valid_signers = 0u8;
+    /// splitmix64
+    struct Rng(u64);
 
-        if multisig_admins.contains(&ctx.accounts.admin.key()) {
-            valid_signers += 1;
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
         }
-        if multisig_admins.contains(&ctx.accounts.signer1.key()) {
-            valid_signers += 1;
+    }
+
+    struct Fighter {
+        stats: HeroStats,
+        hp: u64,
+        skill_cd: u8,
+        ultimate_cd: u8,
+    }
+
+    fn damage(attacker: &HeroStats, defender: &HeroStats, multiplier_bps: u64, rng: &mut Rng) -> u64 {
+        let raw = (attacker.atk as u64).saturating_mul(multiplier_bps) / 10_000;
+        let mut dmg = raw.saturating_mul(100) / (100 + defender.def as u64);
+        if rng.next() % 10_000 < attacker.crit_bps as u64 {
+            dmg = dmg.saturating_mul(CRIT_MULTIPLIER_BPS) / 10_000;
         }
-        if multisig_admins.contains(&ctx.accounts.signer2.key()) {
-            valid_signers += 1;
+        dmg.max(1)
+    }
+
+    /// Runs the battle. Each round every living hero acts once in speed order
+    /// (ties go to team A, then lower slot): ultimate when ready, else skill
+    /// when ready, else a basic attack on the weakest living enemy. An
+    /// ability with cooldown `n` fires on every `n`th turn of its hero.
+    pub fn simulate(team_a: &Team, team_b: &Team, seed: u64) -> Outcome {
+        run(team_a, team_b, seed, &mut |_, _| {})
+    }
+
+    fn run(team_a: &Team, team_b: &Team, seed: u64, on_action: &mut dyn FnMut(usize, Action)) -> Outcome {
+        let mut rng = Rng(seed);
+        let mut fighters: Vec<Fighter> = team_a
+            .iter()
+            .chain(team_b.iter())
+            .map(|stats| Fighter {
+                stats: *stats,
+                hp: stats.hp as u64,
+                skill_cd: stats.skill_cooldown,
+                ultimate_cd: stats.ultimate_cooldown,
+            })
+            .collect();
+
+        let mut order: Vec<usize> = (0..TEAM_SIZE * 2).collect();
+        order.sort_by_key(|i| (core::cmp::Reverse(fighters[*i].stats.spd), *i));
+
+        let alive = |fighters: &Vec<Fighter>, side: usize| {
+            (side * TEAM_SIZE..(side + 1) * TEAM_SIZE).any(|i| fighters[i].hp > 0)
+        };
+
+        for _ in 0..MAX_ROUNDS {
+            for &actor in order.iter() {
+                if fighters[actor].hp == 0 {
+                    continue;
+                }
+                let enemy_side = 1 - actor / TEAM_SIZE;
+                if !alive(&fighters, enemy_side) {
+                    break;
+                }
+                let enemies = enemy_side * TEAM_SIZE..(enemy_side + 1) * TEAM_SIZE;
+                let attacker = fighters[actor].stats;
+
+                // Cooldowns tick before acting, so an ability used on turn t
+                // is ready again on turn t + cooldown
+                let fighter = &mut fighters[actor];
+                fighter.skill_cd = fighter.skill_cd.saturating_sub(1);
+                fighter.ultimate_cd = fighter.ultimate_cd.saturating_sub(1);
+
+                let ultimate_ready = attacker.ultimate_cooldown > 0 && fighters[actor].ultimate_cd == 0;
+                let skill_ready = attacker.skill_cooldown > 0 && fighters[actor].skill_cd == 0;
+
+                if ultimate_ready {
+                    for target in enemies {
+                        if fighters[target].hp > 0 {
+                            let dmg = damage(&attacker, &fighters[target].stats, ULTIMATE_MULTIPLIER_BPS, &mut rng);
+                            fighters[target].hp = fighters[target].hp.saturating_sub(dmg);
+                        }
+                    }
+                    fighters[actor].ultimate_cd = attacker.ultimate_cooldown;
+                    on_action(actor, Action::Ultimate);
+                } else {
+                    let target = enemies
+                        .filter(|i| fighters[*i].hp > 0)
+                        .min_by_key(|i| (fighters[*i].hp, *i))
+                        .unwrap();
+                    let multiplier = if skill_ready { SKILL_MULTIPLIER_BPS } else { 10_000 };
+                    let dmg = damage(&attacker, &fighters[target].stats, multiplier, &mut rng);
+                    fighters[target].hp = fighters[target].hp.saturating_sub(dmg);
+                    if skill_ready {
+                        fighters[actor].skill_cd = attacker.skill_cooldown;
+                    }
+                    on_action(actor, if skill_ready { Action::Skill } else { Action::Attack });
+                }
+            }
+
+            match (alive(&fighters, 0), alive(&fighters, 1)) {
+                (true, false) => return Outcome::TeamA,
+                (false, true) => return Outcome::TeamB,
+                (false, false) => return Outcome::Draw,
+                (true, true) => {}
+            }
         }
 
-        require!(valid_signers >= 2, MarketplaceError::Unauthorized);
+        Outcome::Draw
+    }
 
-        let is_native_sol = ctx.accounts.mint.key() == native_mint::ID;
-        let signer_seeds: &[&[&[u8]]] =
-            &[&[b"treasury", &[ctx.accounts.market_config.treasury_bump]]];
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn hero(hp: u32, atk: u32) -> HeroStats {
+            HeroStats {
+                hp,
+                atk,
+                def: 0,
+                spd: 100,
+                crit_bps: 0,
+                skill_cooldown: 0,
+                ultimate_cooldown: 0,
+            }
+        }
 
-        if is_native_sol {
-            let treasury_info = &mut ctx.accounts.treasury_pda.to_account_info();
-            let admin_info = &mut ctx.accounts.admin.to_account_info();
-            **treasury_info.lamports.borrow_mut() -= amount;
-            **admin_info.lamports.borrow_mut() += amount;
-        } else {
-            let treasury_token = ctx
-                .accounts
-                .treasury_token_account
-                .as_ref()
-                .expect("missing treasury_token_account");
-            let admin_token = ctx
-                .accounts
-                .admin_token_account
-                .as_ref()
-                .expect("missing admin_token_account");
+        fn traced(team_a: &Team, team_b: &Team, seed: u64) -> (Outcome, Vec<(usize, Action)>) {
+            let mut actions = Vec::new();
+            let outcome = run(team_a, team_b, seed, &mut |actor, action| actions.push((actor, action)));
+            (outcome, actions)
+        }
 
-            let cpi_accounts = Transfer {
-                from: treasury_token.to_account_info(),
-                to: admin_token.to_account_info(),
-                authority: ctx.accounts.treasury_pda.to_account_info(),
-            };
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                cpi_accounts,
+        fn actions_of(actions: &[(usize, Action)], actor: usize) -> Vec<Action> {
+            actions.iter().filter(|(a, _)| *a == actor).map(|(_, action)| *action).collect()
+        }
+
+        #[test]
+        fn simulate_is_deterministic() {
+            let team_a = team_for(&[Rarity::Epic, Rarity::Common, Rarity::Rare]);
+            let team_b = team_for(&[Rarity::Rare, Rarity::Rare, Rarity::Common]);
+            for seed in [0, 1, 42, u64::MAX] {
+                assert_eq!(traced(&team_a, &team_b, seed), traced(&team_a, &team_b, seed));
+                assert_eq!(simulate(&team_a, &team_b, seed), traced(&team_a, &team_b, seed).0);
+            }
+        }
+
+        #[test]
+        fn stronger_team_wins_from_either_side() {
+            let strong = team_for(&[Rarity::Legendary; TEAM_SIZE]);
+            let weak = team_for(&[Rarity::Common; TEAM_SIZE]);
+            for seed in [3, 17, 99] {
+                assert_eq!(simulate(&strong, &weak, seed), Outcome::TeamA);
+                assert_eq!(simulate(&weak, &strong, seed), Outcome::TeamB);
+            }
+        }
+
+        #[test]
+        fn unbroken_stalemate_is_a_draw() {
+            let team = [hero(u32::MAX, 1); TEAM_SIZE];
+            let (outcome, actions) = traced(&team, &team, 5);
+            assert_eq!(outcome, Outcome::Draw);
+            assert_eq!(actions.len(), MAX_ROUNDS as usize * TEAM_SIZE * 2);
+        }
+
+        #[test]
+        fn skill_fires_every_cooldown_turns() {
+            let mut team_a = [hero(u32::MAX, 1); TEAM_SIZE];
+            team_a[0].skill_cooldown = 3;
+            let team_b = [hero(u32::MAX, 1); TEAM_SIZE];
+
+            let (_, actions) = traced(&team_a, &team_b, 7);
+            let turns = actions_of(&actions, 0);
+            assert_eq!(turns.len(), MAX_ROUNDS as usize);
+            for (i, action) in turns.iter().enumerate() {
+                let expected = if (i + 1) % 3 == 0 { Action::Skill } else { Action::Attack };
+                assert_eq!(*action, expected, "turn {}", i + 1);
+            }
+        }
+
+        #[test]
+        fn ultimate_takes_priority_over_a_ready_skill() {
+            let mut team_a = [hero(u32::MAX, 1); TEAM_SIZE];
+            team_a[0].skill_cooldown = 1;
+            team_a[0].ultimate_cooldown = 2;
+            let team_b = [hero(u32::MAX, 1); TEAM_SIZE];
+
+            let (_, actions) = traced(&team_a, &team_b, 11);
+            for (i, action) in actions_of(&actions, 0).iter().enumerate() {
+                let expected = if (i + 1) % 2 == 0 { Action::Ultimate } else { Action::Skill };
+                assert_eq!(*action, expected, "turn {}", i + 1);
+            }
+        }
+
+        #[test]
+        fn crit_multiplies_damage() {
+            let defender = HeroStats { def: 100, ..hero(1_000, 0) };
+            let mut attacker = hero(1_000, 1_000);
+            assert_eq!(damage(&attacker, &defender, 10_000, &mut Rng(1)), 500);
+
+            attacker.crit_bps = 10_000;
+            assert_eq!(damage(&attacker, &defender, 10_000, &mut Rng(1)), 750);
+            assert_eq!(damage(&attacker, &defender, SKILL_MULTIPLIER_BPS, &mut Rng(1)), 1_125);
+        }
+
+        #[test]
+        fn damage_is_at_least_one() {
+            let defender = HeroStats { def: u32::MAX, ..hero(1_000, 0) };
+            assert_eq!(damage(&hero(1, 1), &defender, 10_000, &mut Rng(0)), 1);
+        }
+    }
+}
+
+/// Checks a merkle proof built with sorted-pair hashing, where interior
+/// nodes are `hash(0x01 || min(a, b) || max(a, b))`.
+pub fn verify_merkle_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        let (left, right) = if node <= *sibling { (node, *sibling) } else { (*sibling, node) };
+        hashv(&[&[1u8], &left, &right]).to_bytes()
+    });
+    computed == *root
+}
+
+/// Distribution leaf for `claimant` earning `amount` at `index`:
+/// `hash(0x00 || index_le || claimant || amount_le)`. The 0x00 prefix keeps
+/// leaves from colliding with interior nodes.
+pub fn earnings_leaf(index: u32, claimant: &Pubkey, amount: u64) -> [u8; 32] {
+    hashv(&[
+        &[0u8],
+        &index.to_le_bytes(),
+        claimant.as_ref(),
+        &amount.to_le_bytes(),
+    ])
+    .to_bytes()
+}
+
+/// Bytes of a claim bitmap with one bit per leaf.
+pub fn claim_bitmap_len(num_claims: u32) -> usize {
+    (num_claims as usize + 7) / 8
+}
+
+/// Byte offset and mask of leaf `index` in a claim bitmap of `num_claims`
+/// leaves, or `None` when the index is out of range.
+pub fn claim_bit(index: u32, num_claims: u32) -> Option<(usize, u8)> {
+    (index < num_claims).then(|| (index as usize / 8, 1u8 << (index % 8)))
+}
+
+/// Pays the payer's registered referrer `share_bps` of `fee` (in SOL when
+/// `mint` is `None`) and returns what is left for the treasury. Without a
+/// complete set of referral accounts the whole fee stays with the treasury.
+#[allow(clippy::too_many_arguments)]
+pub fn pay_referral_share<'info>(fee: u64, share_bps: u16, payer: &AccountInfo<'info>, payer_token_account: Option<&AccountInfo<'info>>, mint: Option<&InterfaceAccount<'info, Mint>>, token_program: Option<&AccountInfo<'info>>, system_program: Option<&AccountInfo<'info>>, referral: Option<&Account<'info, Referral>>, referrer_stats: Option<&mut Account<'info, Referral>>, referrer_destination: Option<&AccountInfo<'info>>) -> Result<u64> {
+    let (referral, stats, destination) = match (referral, referrer_stats, referrer_destination) {
+        (Some(referral), Some(stats), Some(destination)) => (referral, stats, destination),
+        _ => return Ok(fee),
+    };
+    require_keys_eq!(referral.player, payer.key(), MarketplaceError::InvalidReferral);
+    require!(referral.referrer == Some(stats.player), MarketplaceError::InvalidReferral);
+
+    let share = fee.saturating_mul(share_bps as u64) / 10_000;
+    if share == 0 {
+        return Ok(fee);
+    }
+
+    match mint {
+        None => {
+            require_keys_eq!(destination.key(), stats.player, MarketplaceError::InvalidReferral);
+            invoke(
+                &system_instruction::transfer(&payer.key(), &destination.key(), share),
+                &[
+                    payer.clone(),
+                    destination.clone(),
+                    system_program.ok_or(MarketplaceError::InvalidOwner)?.clone(),
+                ],
+            )?;
+            stats.rewards_lamports = stats.rewards_lamports.saturating_add(share);
+        }
+        Some(mint) => {
+            let account = InterfaceAccount::<TokenAccount>::try_from(destination)?;
+            require_keys_eq!(account.owner, stats.player, MarketplaceError::InvalidReferral);
+            require_keys_eq!(account.mint, mint.key(), MarketplaceError::InvalidMint);
+
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    token_program.ok_or(MarketplaceError::InvalidMint)?.clone(),
+                    TransferChecked {
+                        from: payer_token_account.ok_or(MarketplaceError::InvalidOwner)?.clone(),
+                        mint: mint.to_account_info(),
+                        to: destination.clone(),
+                        authority: payer.clone(),
+                    },
+                ),
+                share,
+                mint.decimals,
+            )?;
+            stats.rewards_tokens = stats.rewards_tokens.saturating_add(share);
+        }
+    }
+    stats.rewarded_trades += 1;
+
+    emit!(ReferralRewarded {
+        referrer: stats.player,
+        player: referral.player,
+        mint: mint.map(|m| m.key()).unwrap_or(native_mint::ID),
+        amount: share,
+    });
+
+    Ok(fee - share)
+}
+
+/// Decodes a `MarketConfig` body (after the discriminator) of any layout the
+/// program has shipped. Fields appended after the original layout default to
+/// zero when the account predates them.
+pub fn read_market_config(mut data: &[u8]) -> Result<MarketConfig> {
+    let admin = Pubkey::deserialize(&mut data)?;
+    let mint_fee_bps = u16::deserialize(&mut data)?;
+    let trade_fee_bps = u16::deserialize(&mut data)?;
+    let relist_fee_bps = u16::deserialize(&mut data)?;
+    let treasury_bump = u8::deserialize(&mut data)?;
+    let multisig_admins = Vec::<Pubkey>::deserialize(&mut data)?;
+    let multisig_threshold = u8::deserialize(&mut data)?;
+    let transfer_fee_bps = u16::deserialize(&mut data).unwrap_or(0);
+    let trade_offer_fee_lamports = u64::deserialize(&mut data).unwrap_or(0);
+    let referral_share_bps = u16::deserialize(&mut data).unwrap_or(0);
+
+    Ok(MarketConfig {
+        admin,
+        mint_fee_bps,
+        trade_fee_bps,
+        relist_fee_bps,
+        treasury_bump,
+        multisig_admins,
+        multisig_threshold,
+        transfer_fee_bps,
+        trade_offer_fee_lamports,
+        referral_share_bps,
+    })
+}
+
+/// Checks that `player` holds each hero in `accounts` and returns the hero
+/// mints and their registry rarities. Each hero is given as its token account
+/// followed by its `HeroRarity` entry; a hero the player is renting sits in
+/// its rental vault, so its `RentalListing` goes between the two.
+pub fn commit_team<'info>(player: &Pubkey, accounts: &[AccountInfo<'info>]) -> Result<([Pubkey; battle::TEAM_SIZE], [Rarity; battle::TEAM_SIZE])> {
+    let now = Clock::get()?.unix_timestamp;
+    let mut accounts = accounts.iter();
+    let mut next = || accounts.next().ok_or(MarketplaceError::InvalidWager);
+
+    let mut mints = [Pubkey::default(); battle::TEAM_SIZE];
+    let mut rarities = [Rarity::Common; battle::TEAM_SIZE];
+    for slot in 0..battle::TEAM_SIZE {
+        let hero = InterfaceAccount::<TokenAccount>::try_from(next()?)?;
+        require!(hero.amount == 1, MarketplaceError::InvalidNFT);
+        require!(!mints[..slot].contains(&hero.mint), MarketplaceError::InvalidWager);
+        if hero.owner != *player {
+            let rental = Account::<RentalListing>::try_from(next()?)?;
+            require_keys_eq!(hero.owner, rental.key(), MarketplaceError::InvalidOwner);
+            require_keys_eq!(rental.nft_mint, hero.mint, MarketplaceError::InvalidNFT);
+            require!(
+                rental.renter == Some(*player) && rental.rented_until > now,
+                MarketplaceError::InvalidOwner
+            );
+        }
+
+        let registry = Account::<HeroRarity>::try_from(next()?)?;
+        require_keys_eq!(registry.nft_mint, hero.mint, MarketplaceError::InvalidNFT);
+
+        mints[slot] = hero.mint;
+        rarities[slot] = registry.rarity;
+    }
+    require!(next().is_err(), MarketplaceError::InvalidWager);
+    Ok((mints, rarities))
+}
+
+/// Splits a wager pot by `outcome`: the winner takes it less `fee_bps`, a
+/// draw splits it fee-free. The vault is closed to `rent_destination`.
+/// Returns `(payout_a, payout_b, fee)`.
+#[allow(clippy::too_many_arguments)]
+pub fn pay_out_wager<'info>(token_program: &AccountInfo<'info>, mint: &InterfaceAccount<'info, Mint>, vault: &InterfaceAccount<'info, TokenAccount>, player_a_token_account: &AccountInfo<'info>, player_b_token_account: &AccountInfo<'info>, treasury_token_account: &AccountInfo<'info>, rent_destination: &AccountInfo<'info>, signer_seeds: &[&[&[u8]]], fee_bps: u16, outcome: battle::Outcome) -> Result<(u64, u64, u64)> {
+    let pot = vault.amount;
+    let (payout_a, payout_b, fee) = match outcome {
+        battle::Outcome::Draw => (pot - pot / 2, pot / 2, 0),
+        _ => {
+            let fee = pot.saturating_mul(fee_bps as u64) / 10_000;
+            if outcome == battle::Outcome::TeamA {
+                (pot - fee, 0, fee)
+            } else {
+                (0, pot - fee, fee)
+            }
+        }
+    };
+
+    for (destination, amount) in [(treasury_token_account, fee), (player_b_token_account, payout_b)] {
+        if amount == 0 {
+            continue;
+        }
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                TransferChecked {
+                    from: vault.to_account_info(),
+                    mint: mint.to_account_info(),
+                    to: destination.clone(),
+                    authority: vault.to_account_info(),
+                },
                 signer_seeds,
+            ),
+            amount,
+            mint.decimals,
+        )?;
+    }
+
+    release_from_vault(
+        token_program,
+        &mint.to_account_info(),
+        &vault.to_account_info(),
+        player_a_token_account,
+        &vault.to_account_info(),
+        rent_destination,
+        signer_seeds,
+        payout_a,
+    )?;
+
+    Ok((payout_a, payout_b, fee))
+}
+
+#[program]
+pub mod universe_of_gamers {
+    use super::*;
+
+    pub fn initialize_market(ctx: Context<InitializeMarket>, mint_fee_bps: u16, trade_fee_bps: u16, relist_fee_bps: u16, transfer_fee_bps: u16, multisig_admins: Vec<Pubkey>, multisig_threshold: u8) -> Result<()> {
+        require!(multisig_threshold > 0, MarketplaceError::InvalidThreshold);
+        require!(transfer_fee_bps <= 10_000, MarketplaceError::InvalidFeeBps);
+        require!(
+            multisig_threshold as usize <= multisig_admins.len() + 1,
+            MarketplaceError::InvalidThreshold
+        );
+
+        let cfg = &mut ctx.accounts.market_config;
+        cfg.admin = ctx.accounts.admin.key();
+        cfg.mint_fee_bps = mint_fee_bps;
+        cfg.trade_fee_bps = trade_fee_bps;
+        cfg.relist_fee_bps = relist_fee_bps;
+        cfg.transfer_fee_bps = transfer_fee_bps;
+        cfg.treasury_bump = *ctx.bumps.get("treasury_pda").unwrap();
+        cfg.multisig_admins = multisig_admins;
+        cfg.multisig_threshold = multisig_threshold;
+
+        emit!(MarketInitialized {
+            admin: cfg.admin,
+            mint_fee_bps,
+            trade_fee_bps,
+            relist_fee_bps,
+            transfer_fee_bps,
+            multisig_threshold,
+        });
+
+        Ok(())
+    }
+
+    /// Grows a `MarketConfig` written by an older program version to
+    /// `MARKET_CONFIG_SPACE` and fills the fields appended since then.
+    /// Works on the raw account because a config with five admins has no
+    /// spare bytes and cannot be deserialized until it has been migrated.
+    pub fn migrate_market_config(ctx: Context<MigrateMarketConfig>) -> Result<()> {
+        let config_info = ctx.accounts.market_config.to_account_info();
+        let previous_len = config_info.data_len();
+
+        let cfg = {
+            let data = config_info.try_borrow_data()?;
+            require!(
+                data.len() >= 8 && data[..8] == MarketConfig::DISCRIMINATOR,
+                MarketplaceError::InvalidMarketConfig
             );
-            token::transfer(cpi_ctx, amount)?;
+            read_market_config(&data[8..])?
+        };
+
+        require_keys_eq!(cfg.admin, ctx.accounts.admin.key(), MarketplaceError::Unauthorized);
+        require_multisig(
+            &cfg,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+
+        if previous_len < MARKET_CONFIG_SPACE {
+            let rent_due = Rent::get()?
+                .minimum_balance(MARKET_CONFIG_SPACE)
+                .saturating_sub(config_info.lamports());
+            if rent_due > 0 {
+                invoke(
+                    &system_instruction::transfer(&ctx.accounts.admin.key(), &config_info.key(), rent_due),
+                    &[
+                        ctx.accounts.admin.to_account_info(),
+                        config_info.clone(),
+                        ctx.accounts.system_program.to_account_info(),
+                    ],
+                )?;
+            }
+            config_info.realloc(MARKET_CONFIG_SPACE, true)?;
         }
 
+        let mut data = config_info.try_borrow_mut_data()?;
+        let mut writer: &mut [u8] = &mut data[..];
+        cfg.try_serialize(&mut writer)?;
+
+        emit!(MarketConfigMigrated {
+            previous_len: previous_len as u32,
+            new_len: MARKET_CONFIG_SPACE as u32,
+        });
+
         Ok(())
     }
 
-    pub fn send_token(ctx: Context<SendToken>, amount: u64) -> Result<()> {
-        let fee = amount
-            .saturating_mul(ctx.accounts.market_config.trade_fee_bps as u64)
-            / 10_000;
-        let recipient_amount = amount.saturating_sub(fee);
+    pub fn create_fee_tier(ctx: Context<CreateFeeTier>, subject: Pubkey, kind: FeeTierKind, min_balance: u64, discount_bps: u16) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+        require!(discount_bps <= 10_000, MarketplaceError::InvalidFeeBps);
+
+        let tier = &mut ctx.accounts.fee_tier;
+        tier.subject = subject;
+        tier.kind = kind;
+        tier.min_balance = min_balance;
+        tier.discount_bps = discount_bps;
+        tier.bump = *ctx.bumps.get("fee_tier").unwrap();
+
+        emit!(FeeTierSet {
+            subject,
+            kind,
+            min_balance,
+            discount_bps,
+        });
 
-        let use_sol = ctx.accounts.mint.key() == native_mint::id();
+        Ok(())
+    }
 
-        let sender_token_info = ctx.accounts.sender_token_account.to_account_info();
-        let recipient_token_info = ctx.accounts.recipient_token_account.to_account_info();
-        let treasury_token_info = ctx.accounts.treasury_token_account.to_account_info();
-        let token_program_info = ctx.accounts.token_program.to_account_info();
-        let system_program_info = ctx.accounts.system_program.to_account_info();
+    pub fn update_fee_tier(ctx: Context<UpdateFeeTier>, min_balance: u64, discount_bps: u16) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+        require!(discount_bps <= 10_000, MarketplaceError::InvalidFeeBps);
 
-        pay_fee(
-            use_sol,
-            &ctx.accounts.sender.to_account_info(),
-            &ctx.accounts.recipient.to_account_info(),
-            if use_sol { None } else { Some(&sender_token_info) },
-            if use_sol { None } else { Some(&recipient_token_info) },
-            &token_program_info,
-            &system_program_info,
-            recipient_amount,
+        let tier = &mut ctx.accounts.fee_tier;
+        tier.min_balance = min_balance;
+        tier.discount_bps = discount_bps;
+
+        emit!(FeeTierSet {
+            subject: tier.subject,
+            kind: tier.kind,
+            min_balance,
+            discount_bps,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_fee_tier(ctx: Context<RemoveFeeTier>) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
         )?;
 
-        pay_fee(
-            use_sol,
-            &ctx.accounts.sender.to_account_info(),
-            &ctx.accounts.treasury_pda.to_account_info(),
-            if use_sol { None } else { Some(&sender_token_info) },
-            if use_sol { None } else { Some(&treasury_token_info) },
-            &token_program_info,
-            &system_program_info,
-            fee,
+        emit!(FeeTierRemoved {
+            subject: ctx.accounts.fee_tier.subject,
+        });
+
+        Ok(())
+    }
+
+    pub fn add_allowed_collection(ctx: Context<AddAllowedCollection>, collection_mint: Pubkey) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
         )?;
 
+        let allowed = &mut ctx.accounts.allowed_collection;
+        allowed.collection_mint = collection_mint;
+        allowed.bump = *ctx.bumps.get("allowed_collection").unwrap();
+
+        emit!(CollectionAllowed { collection_mint });
+
         Ok(())
     }
 
-    pub fn swap_token(ctx: Context<SwapToken>, data: Vec<u8>, _in_amount: u64) -> Result<()> {
-        let user_out_account_info = &ctx.remaining_accounts[0];
-        let user_out_before;
-        let output_mint;
+    pub fn remove_allowed_collection(ctx: Context<RemoveAllowedCollection>) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
 
-        if let Ok(acc) = Account::<TokenAccount>::try_from(user_out_account_info) {
-            require!(acc.owner == ctx.accounts.user.key(), MarketplaceError::InvalidOwner);
-            user_out_before = acc.amount;
-            output_mint = acc.mint;
-        } else {
-            user_out_before = user_out_account_info.lamports();
-            output_mint = native_mint::id();
-        }
+        emit!(CollectionDisallowed {
+            collection_mint: ctx.accounts.allowed_collection.collection_mint,
+        });
 
-        let ix = Instruction {
-            program_id: ctx.accounts.dex_program.key(),
-            accounts: ctx
-                .remaining_accounts
-                .iter()
-                .map(|acc| AccountMeta {
-                    pubkey: acc.key(),
-                    is_signer: acc.is_signer,
-                    is_writable: acc.is_writable,
-                })
-                .collect(),
-            data,
-        };
-        invoke(&ix, &ctx.remaining_accounts)?;
+        Ok(())
+    }
 
-        let user_out_after = if let Ok(acc) = Account::<TokenAccount>::try_from(user_out_account_info) {
-            acc.amount
-        } else {
-            user_out_account_info.lamports()
-        };
+    pub fn set_trade_offer_fee(ctx: Context<SetTradeOfferFee>, trade_offer_fee_lamports: u64) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
 
-        let out_amount = user_out_after.saturating_sub(user_out_before);
+        ctx.accounts.market_config.trade_offer_fee_lamports = trade_offer_fee_lamports;
 
-        let trade_fee_bps = ctx.accounts.market_config.trade_fee_bps as u64;
-        let trade_fee = out_amount.saturating_mul(trade_fee_bps) / 10_000;
+        emit!(TradeOfferFeeUpdated { trade_offer_fee_lamports });
 
-        if trade_fee > 0 {
-            let user_acc = ctx
-                .remaining_accounts
-                .iter()
-                .find(|a| a.key() == ctx.accounts.user.key())
-                .ok_or(MarketplaceError::InvalidOwner)?;
+        Ok(())
+    }
+
+    pub fn initialize_treasury(_ctx: Context<InitializeTreasury>) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn mint_and_list(ctx: Context<MintAndList>, price: u64, use_sol: bool, max_mint_fee: u64, name: String, symbol: String, uri: String, _seller_fee_basis_points: u16) -> Result<()> {
+        token_interface::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.seller_nft_ata.to_account_info(),
+                    authority: ctx.accounts.mint_authority.to_account_info(),
+                },
+                &[&[
+                    b"mint_auth",
+                    ctx.accounts.mint.key().as_ref(),
+                    &[*ctx.bumps.get("mint_authority").unwrap()],
+                ]],
+            ),
+            1,
+        )?;
+
+        if has_embedded_metadata(&ctx.accounts.mint)? {
+            fund_embedded_metadata_rent(
+                &ctx.accounts.seller.to_account_info(),
+                &ctx.accounts.mint.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                &name,
+                &symbol,
+                &uri,
+            )?;
+
+            invoke_signed(
+                &spl_token_metadata_interface::instruction::initialize(
+                    &token_2022::ID,
+                    &ctx.accounts.mint.key(),
+                    &ctx.accounts.update_authority.key(),
+                    &ctx.accounts.mint.key(),
+                    &ctx.accounts.mint_authority.key(),
+                    name,
+                    symbol,
+                    uri,
+                ),
+                &[
+                    ctx.accounts.mint.to_account_info(),
+                    ctx.accounts.update_authority.to_account_info(),
+                    ctx.accounts.mint_authority.to_account_info(),
+                ],
+                &[&[
+                    b"mint_auth",
+                    ctx.accounts.mint.key().as_ref(),
+                    &[*ctx.bumps.get("mint_authority").unwrap()],
+                ]],
+            )?;
+        }
+
+        token_interface::set_authority(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                SetAuthority {
+                    current_authority: ctx.accounts.mint_authority.to_account_info(),
+                    account_or_mint: ctx.accounts.mint.to_account_info(),
+                },
+                &[&[
+                    b"mint_auth",
+                    ctx.accounts.mint.key().as_ref(),
+                    &[*ctx.bumps.get("mint_authority").unwrap()],
+                ]],
+            ),
+            AuthorityType::MintTokens,
+            None,
+        )?;
+
+        token_interface::approve(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::Approve {
+                    to: ctx.accounts.seller_nft_ata.to_account_info(),
+                    delegate: ctx.accounts.escrow_signer.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+
+        let mint_fee_bps = ctx.accounts.market_config.mint_fee_bps as u64;
+        let expected_fee = price
+            .saturating_mul(mint_fee_bps)
+            .checked_div(10_000)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        // The SPL fee leg also covers the payment mint's transfer fee
+        let fee_sent = if use_sol {
+            expected_fee
+        } else {
+            gross_up_transfer_fee(&ctx.accounts.spl_mint, expected_fee)?
+        };
+        require!(fee_sent <= max_mint_fee, MarketplaceError::FeeExceedsMax);
+
+        // Both legs land in the treasury PDA (SOL) or its token account (SPL)
+        let seller_payment_info = ctx.accounts.seller_payment_ata.as_ref().map(|a| a.to_account_info());
+        let treasury_token_info = ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info());
+        let payment_token_program_info = ctx.accounts.payment_token_program.to_account_info();
+        let system_program_info = ctx.accounts.system_program.to_account_info();
+
+        for amount in [price, fee_sent] {
+            pay_fee(
+                use_sol,
+                &ctx.accounts.seller.to_account_info(),
+                &ctx.accounts.treasury_pda.to_account_info(),
+                seller_payment_info.as_ref(),
+                treasury_token_info.as_ref(),
+                &ctx.accounts.spl_mint,
+                &payment_token_program_info,
+                &system_program_info,
+                amount,
+            )?;
+        }
+
+        let listing = &mut ctx.accounts.listing;
+        listing.seller = ctx.accounts.seller.key();
+        listing.nft_mint = ctx.accounts.mint.key();
+        listing.price = price;
+        listing.use_sol = use_sol;
+        listing.bump = *ctx.bumps.get("listing").unwrap();
+        listing.mode = ListingMode::Delegate;
+        listing.payment_mint = if use_sol { native_mint::id() } else { ctx.accounts.spl_mint.key() };
+
+        emit!(NftMintedAndListed {
+            seller: listing.seller,
+            nft_mint: listing.nft_mint,
+            price,
+            use_sol,
+            mint_fee: expected_fee,
+        });
+
+        Ok(())
+    }
+
+    pub fn buy_nft(ctx: Context<BuyNft>) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+
+        let trade_fee_bps = discounted_fee_bps(
+            ctx.accounts.market_config.trade_fee_bps,
+            ctx.accounts.buyer.key(),
+            ctx.accounts.fee_tier.as_ref(),
+            ctx.accounts.fee_tier_holding.as_ref(),
+            ctx.accounts.fee_tier_stake.as_ref(),
+        )?;
+        let trade_fee = listing
+            .price
+            .saturating_mul(trade_fee_bps as u64)
+            / 10_000;
+        let seller_amount = listing.price.saturating_sub(trade_fee);
+
+        let is_sol_payment = listing.use_sol;
+        let buyer_payment_info = ctx.accounts.buyer_payment_ata.as_ref().map(|a| a.to_account_info());
+        let seller_payment_info = ctx.accounts.seller_payment_ata.as_ref().map(|a| a.to_account_info());
+        let treasury_token_info = ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info());
+        let payment_token_program_info = ctx.accounts.payment_token_program.to_account_info();
+        let system_program_info = ctx.accounts.system_program.to_account_info();
+
+        let escrow_signer_bump = *ctx.bumps.get("escrow_signer").unwrap_or(&listing.bump);
+
+        let referrer_destination_info = ctx.accounts.referrer_destination.as_ref().map(|a| a.to_account_info());
+        let treasury_fee = pay_referral_share(
+            trade_fee,
+            ctx.accounts.market_config.referral_share_bps,
+            &ctx.accounts.buyer.to_account_info(),
+            buyer_payment_info.as_ref(),
+            if is_sol_payment { None } else { Some(&ctx.accounts.payment_mint) },
+            Some(&payment_token_program_info),
+            Some(&system_program_info),
+            ctx.accounts.referral.as_ref(),
+            ctx.accounts.referrer_stats.as_mut(),
+            referrer_destination_info.as_ref(),
+        )?;
+
+        // The seller bears a Token-2022 transfer fee on their proceeds; the
+        // treasury leg is grossed up so the market still nets its fee.
+        let seller_proceeds = seller_amount
+            .saturating_sub(transfer_fee_for(&ctx.accounts.payment_mint, seller_amount)?);
+        let treasury_fee_sent = gross_up_transfer_fee(&ctx.accounts.payment_mint, treasury_fee)?;
+
+        pay_fee(
+            is_sol_payment,
+            &ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.seller.to_account_info(),
+            buyer_payment_info.as_ref(),
+            seller_payment_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &payment_token_program_info,
+            &system_program_info,
+            seller_amount,
+        )?;
+
+        pay_fee(
+            is_sol_payment,
+            &ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.treasury_pda.to_account_info(),
+            buyer_payment_info.as_ref(),
+            treasury_token_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &payment_token_program_info,
+            &system_program_info,
+            treasury_fee_sent,
+        )?;
+
+        let signer_seeds: &[&[u8]] = &[
+            b"escrow_signer",
+            listing.nft_mint.as_ref(),
+            &[escrow_signer_bump],
+        ];
+
+        let (source, source_owner) = match listing.mode {
+            ListingMode::Delegate => (
+                ctx.accounts
+                    .seller_nft_ata
+                    .as_ref()
+                    .ok_or(MarketplaceError::InvalidNFT)?
+                    .to_account_info(),
+                ctx.accounts.seller.to_account_info(),
+            ),
+            ListingMode::Custody => (
+                ctx.accounts
+                    .escrow_vault
+                    .as_ref()
+                    .ok_or(MarketplaceError::MissingEscrowVault)?
+                    .to_account_info(),
+                ctx.accounts.escrow_signer.to_account_info(),
+            ),
+        };
+
+        let programmable = ctx.accounts.pnft.is_programmable(&listing.nft_mint)?;
+        if programmable {
+            ctx.accounts.pnft.transfer(
+                &source,
+                &source_owner,
+                &ctx.accounts.buyer_nft_ata.to_account_info(),
+                &ctx.accounts.buyer.to_account_info(),
+                &ctx.accounts.nft_mint.to_account_info(),
+                &ctx.accounts.escrow_signer.to_account_info(),
+                &ctx.accounts.buyer.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.token_program.to_account_info(),
+                &[signer_seeds],
+            )?;
+        } else {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: source.clone(),
+                        mint: ctx.accounts.nft_mint.to_account_info(),
+                        to: ctx.accounts.buyer_nft_ata.to_account_info(),
+                        authority: ctx.accounts.escrow_signer.to_account_info(),
+                    },
+                    &[signer_seeds],
+                ),
+                1,
+                ctx.accounts.nft_mint.decimals,
+            )?;
+        }
+
+        if listing.mode == ListingMode::Custody && !programmable {
+            token_interface::close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::CloseAccount {
+                    account: source,
+                    destination: ctx.accounts.seller.to_account_info(),
+                    authority: ctx.accounts.escrow_signer.to_account_info(),
+                },
+                &[signer_seeds],
+            ))?;
+        }
+
+        emit!(NftSold {
+            nft_mint: listing.nft_mint,
+            buyer: ctx.accounts.buyer.key(),
+            seller: listing.seller,
+            price: listing.price,
+            fee: trade_fee,
+            seller_proceeds,
+            use_sol: is_sol_payment,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>, amount: u64) -> Result<()> {
+        let valid_signers = count_multisig_signers(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        );
+
+        // Treasury withdrawals always need two distinct admins, whatever the
+        // configured threshold
+        require!(
+            valid_signers >= ctx.accounts.market_config.multisig_threshold.max(2),
+            MarketplaceError::Unauthorized
+        );
+
+        let is_native_sol = ctx.accounts.mint.key() == native_mint::ID;
+        let signer_seeds: &[&[&[u8]]] =
+            &[&[b"treasury", &[ctx.accounts.market_config.treasury_bump]]];
+
+        if is_native_sol {
+            let treasury_info = &mut ctx.accounts.treasury_pda.to_account_info();
+            let admin_info = &mut ctx.accounts.admin.to_account_info();
+            **treasury_info.lamports.borrow_mut() -= amount;
+            **admin_info.lamports.borrow_mut() += amount;
+        } else {
+            let treasury_token = ctx
+                .accounts
+                .treasury_token_account
+                .as_ref()
+                .expect("missing treasury_token_account");
+            let admin_token = ctx
+                .accounts
+                .admin_token_account
+                .as_ref()
+                .expect("missing admin_token_account");
+
+            let cpi_accounts = TransferChecked {
+                from: treasury_token.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: admin_token.to_account_info(),
+                authority: ctx.accounts.treasury_pda.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                signer_seeds,
+            );
+            token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+        }
+
+        emit!(TreasuryWithdrawn {
+            mint: ctx.accounts.mint.key(),
+            recipient: ctx.accounts.admin.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn send_token(ctx: Context<SendToken>, amount: u64) -> Result<()> {
+        let transfer_fee_bps = discounted_fee_bps(
+            ctx.accounts.market_config.transfer_fee_bps,
+            ctx.accounts.sender.key(),
+            ctx.accounts.fee_tier.as_ref(),
+            ctx.accounts.fee_tier_holding.as_ref(),
+            ctx.accounts.fee_tier_stake.as_ref(),
+        )?;
+        let fee = amount
+            .saturating_mul(transfer_fee_bps as u64)
+            / 10_000;
+        let recipient_amount = amount.saturating_sub(fee);
+
+        let use_sol = ctx.accounts.mint.key() == native_mint::id();
+        let withheld = if use_sol {
+            0
+        } else {
+            transfer_fee_for(&ctx.accounts.mint, recipient_amount)?
+        };
+
+        // Token accounts are only required on the SPL path; native SOL moves lamports directly
+        let sender_token_info = ctx.accounts.sender_token_account.as_ref().map(|a| a.to_account_info());
+        let recipient_token_info = ctx.accounts.recipient_token_account.as_ref().map(|a| a.to_account_info());
+        let treasury_token_info = ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info());
+        let token_program_info = ctx.accounts.token_program.to_account_info();
+        let system_program_info = ctx.accounts.system_program.to_account_info();
+
+        pay_fee(
+            use_sol,
+            &ctx.accounts.sender.to_account_info(),
+            &ctx.accounts.recipient.to_account_info(),
+            sender_token_info.as_ref(),
+            recipient_token_info.as_ref(),
+            &ctx.accounts.mint,
+            &token_program_info,
+            &system_program_info,
+            recipient_amount,
+        )?;
+
+        pay_fee(
+            use_sol,
+            &ctx.accounts.sender.to_account_info(),
+            &ctx.accounts.treasury_pda.to_account_info(),
+            sender_token_info.as_ref(),
+            treasury_token_info.as_ref(),
+            &ctx.accounts.mint,
+            &token_program_info,
+            &system_program_info,
+            fee,
+        )?;
+
+        emit!(TokenSent {
+            sender: ctx.accounts.sender.key(),
+            recipient: ctx.accounts.recipient.key(),
+            mint: ctx.accounts.mint.key(),
+            amount: recipient_amount.saturating_sub(withheld),
+            fee,
+        });
+
+        Ok(())
+    }
+
+    pub fn swap_token(ctx: Context<SwapToken>, data: Vec<u8>) -> Result<()> {
+        let user_out_account_info = &ctx.remaining_accounts[0];
+        let user_out_before;
+        let output_mint;
+
+        if let Ok(acc) = InterfaceAccount::<TokenAccount>::try_from(user_out_account_info) {
+            require!(acc.owner == ctx.accounts.user.key(), MarketplaceError::InvalidOwner);
+            user_out_before = acc.amount;
+            output_mint = acc.mint;
+        } else {
+            user_out_before = user_out_account_info.lamports();
+            output_mint = native_mint::id();
+        }
+
+        // Input is measured rather than echoed from the caller: the input
+        // token account when one is given, the user's lamports otherwise
+        let user_in_before = match ctx.accounts.user_in_token_account.as_ref() {
+            Some(acc) => acc.amount,
+            None => ctx.accounts.user.lamports(),
+        };
+
+        let ix = Instruction {
+            program_id: ctx.accounts.dex_program.key(),
+            accounts: ctx
+                .remaining_accounts
+                .iter()
+                .map(|acc| AccountMeta {
+                    pubkey: acc.key(),
+                    is_signer: acc.is_signer,
+                    is_writable: acc.is_writable,
+                })
+                .collect(),
+            data,
+        };
+        invoke(&ix, &ctx.remaining_accounts)?;
+
+        let user_out_after = if let Ok(acc) = InterfaceAccount::<TokenAccount>::try_from(user_out_account_info) {
+            acc.amount
+        } else {
+            user_out_account_info.lamports()
+        };
+
+        let out_amount = user_out_after.saturating_sub(user_out_before);
+
+        let user_in_after = match ctx.accounts.user_in_token_account.as_mut() {
+            Some(acc) => {
+                acc.reload()?;
+                acc.amount
+            }
+            None => ctx.accounts.user.lamports(),
+        };
+        let in_amount = user_in_before.saturating_sub(user_in_after);
+
+        let trade_fee_bps = discounted_fee_bps(
+            ctx.accounts.market_config.trade_fee_bps,
+            ctx.accounts.user.key(),
+            ctx.accounts.fee_tier.as_ref(),
+            ctx.accounts.fee_tier_holding.as_ref(),
+            ctx.accounts.fee_tier_stake.as_ref(),
+        )? as u64;
+        let trade_fee = out_amount.saturating_mul(trade_fee_bps) / 10_000;
+
+        if trade_fee > 0 {
+            let user_acc = ctx
+                .remaining_accounts
+                .iter()
+                .find(|a| a.key() == ctx.accounts.user.key())
+                .ok_or(MarketplaceError::InvalidOwner)?;
+
+            let treasury_pda_acc = ctx
+                .remaining_accounts
+                .iter()
+                .find(|a| a.key() == ctx.accounts.treasury_pda.key())
+                .ok_or(MarketplaceError::InvalidOwner)?;
+
+            let treasury_token_acc = ctx
+                .remaining_accounts
+                .iter()
+                .find(|a| a.key() == ctx.accounts.treasury_token_account.key())
+                .ok_or(MarketplaceError::InvalidOwner)?;
+
+            let sys_acc = ctx
+                .remaining_accounts
+                .iter()
+                .find(|a| a.key() == anchor_lang::system_program::ID);
+
+            let token_program_id = *ctx.accounts.output_mint.to_account_info().owner;
+            let token_acc = ctx
+                .remaining_accounts
+                .iter()
+                .find(|a| a.key() == token_program_id);
+
+            let user_out_acc = ctx
+                .remaining_accounts
+                .iter()
+                .find(|a| a.key() == ctx.accounts.user_out_token_account.key());
+
+            let is_sol_output = output_mint == native_mint::id();
+            let referrer_destination_info = ctx.accounts.referrer_destination.as_ref().map(|a| a.to_account_info());
+            let treasury_fee = pay_referral_share(
+                trade_fee,
+                ctx.accounts.market_config.referral_share_bps,
+                user_acc,
+                user_out_acc,
+                if is_sol_output { None } else { Some(&ctx.accounts.output_mint) },
+                token_acc,
+                sys_acc,
+                ctx.accounts.referral.as_ref(),
+                ctx.accounts.referrer_stats.as_mut(),
+                referrer_destination_info.as_ref(),
+            )?;
+
+            if is_sol_output {
+                let sys_acc = sys_acc.ok_or(MarketplaceError::InvalidOwner)?;
+
+                invoke(
+                    &system_instruction::transfer(
+                        &ctx.accounts.user.key(),
+                        &ctx.accounts.treasury_pda.key(),
+                        treasury_fee,
+                    ),
+                    &[user_acc.clone(), treasury_pda_acc.clone(), sys_acc.clone()],
+                )?;
+            } else {
+                let token_acc = token_acc.ok_or(MarketplaceError::InvalidOwner)?;
+                let user_out_acc = user_out_acc.ok_or(MarketplaceError::InvalidOwner)?;
+
+                let ix = token_instruction::transfer_checked(
+                    &token_program_id,
+                    &ctx.accounts.user_out_token_account.key(),
+                    &ctx.accounts.output_mint.key(),
+                    &ctx.accounts.treasury_token_account.key(),
+                    &ctx.accounts.user.key(),
+                    &[],
+                    treasury_fee,
+                    ctx.accounts.output_mint.decimals,
+                )?;
+
+                invoke(
+                    &ix,
+                    &[
+                        user_out_acc.clone(),
+                        ctx.accounts.output_mint.to_account_info(),
+                        treasury_token_acc.clone(),
+                        user_acc.clone(),
+                        token_acc.clone(),
+                    ],
+                )?;
+            }
+        }
+
+        emit!(TokenSwapped {
+            user: ctx.accounts.user.key(),
+            dex_program: ctx.accounts.dex_program.key(),
+            output_mint,
+            in_amount,
+            out_amount,
+            fee: trade_fee,
+        });
+
+        Ok(())
+    }
+
+    pub fn relist_nft(ctx: Context<RelistNft>, new_price: u64, use_sol: bool, max_relist_fee: u64) -> Result<()> {
+        let relist_fee = new_price
+            .saturating_mul(ctx.accounts.market_config.relist_fee_bps as u64)
+            / 10_000;
+        let relist_fee_sent = gross_up_transfer_fee(&ctx.accounts.payment_mint, relist_fee)?;
+        require!(relist_fee_sent <= max_relist_fee, MarketplaceError::FeeExceedsMax);
+
+        let seller_payment_info = ctx.accounts.seller_payment_ata.as_ref().map(|a| a.to_account_info());
+        let treasury_token_info = ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info());
+
+        pay_fee(
+            use_sol,
+            &ctx.accounts.new_owner.to_account_info(),
+            &ctx.accounts.treasury_pda.to_account_info(),
+            seller_payment_info.as_ref(),
+            treasury_token_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.payment_token_program.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            relist_fee_sent,
+        )?;
+
+        let listing = &mut ctx.accounts.listing;
+
+        require_keys_eq!(
+            ctx.accounts.new_owner.key(),
+            ctx.accounts.seller_nft_ata.owner,
+            MarketplaceError::InvalidOwner
+        );
+        require_keys_eq!(
+            listing.nft_mint,
+            ctx.accounts.mint.key(),
+            MarketplaceError::InvalidNFT
+        );
+
+        require!(
+            use_sol == (ctx.accounts.payment_mint.key() == native_mint::id()),
+            MarketplaceError::InvalidMint
+        );
+
+        listing.seller = ctx.accounts.new_owner.key();
+        listing.price = new_price;
+        listing.use_sol = use_sol;
+        listing.mode = ListingMode::Delegate;
+        listing.payment_mint = ctx.accounts.payment_mint.key();
+
+        if ctx.accounts.pnft.is_programmable(&ctx.accounts.mint.key())? {
+            ctx.accounts.pnft.delegate_transfer(
+                &ctx.accounts.escrow_signer.to_account_info(),
+                &ctx.accounts.mint.to_account_info(),
+                &ctx.accounts.seller_nft_ata.to_account_info(),
+                &ctx.accounts.new_owner.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.token_program.to_account_info(),
+            )?;
+        } else {
+            token_interface::approve(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::Approve {
+                        to: ctx.accounts.seller_nft_ata.to_account_info(),
+                        delegate: ctx.accounts.escrow_signer.to_account_info(),
+                        authority: ctx.accounts.new_owner.to_account_info(),
+                    },
+                ),
+                1,
+            )?;
+        }
+
+        emit!(NftRelisted {
+            nft_mint: listing.nft_mint,
+            seller: listing.seller,
+            price: new_price,
+            use_sol,
+            relist_fee,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_hero_tree(ctx: Context<CreateHeroTree>, max_depth: u32, max_buffer_size: u32) -> Result<()> {
+        let hero_tree = &mut ctx.accounts.hero_tree;
+        hero_tree.merkle_tree = ctx.accounts.merkle_tree.key();
+        hero_tree.collection_mint = ctx.accounts.collection_mint.key();
+        hero_tree.minted = 0;
+        hero_tree.bump = *ctx.bumps.get("hero_tree").unwrap();
+
+        let merkle_tree_key = ctx.accounts.merkle_tree.key();
+        let signer_seeds: &[&[u8]] = &[
+            b"hero_tree",
+            merkle_tree_key.as_ref(),
+            &[hero_tree.bump],
+        ];
+
+        CreateTreeConfigCpiBuilder::new(&ctx.accounts.bubblegum_program.to_account_info())
+            .tree_config(&ctx.accounts.tree_config.to_account_info())
+            .merkle_tree(&ctx.accounts.merkle_tree.to_account_info())
+            .payer(&ctx.accounts.admin.to_account_info())
+            .tree_creator(&ctx.accounts.hero_tree.to_account_info())
+            .log_wrapper(&ctx.accounts.log_wrapper.to_account_info())
+            .compression_program(&ctx.accounts.compression_program.to_account_info())
+            .system_program(&ctx.accounts.system_program.to_account_info())
+            .max_depth(max_depth)
+            .max_buffer_size(max_buffer_size)
+            .public(false)
+            .invoke_signed(&[signer_seeds])?;
+
+        emit!(HeroTreeCreated {
+            merkle_tree: merkle_tree_key,
+            collection_mint: ctx.accounts.collection_mint.key(),
+            max_depth,
+            max_buffer_size,
+        });
+
+        Ok(())
+    }
+
+    pub fn mint_compressed_hero(ctx: Context<MintCompressedHero>, name: String, symbol: String, uri: String, seller_fee_basis_points: u16) -> Result<()> {
+        let merkle_tree_key = ctx.accounts.merkle_tree.key();
+        let signer_seeds: &[&[u8]] = &[
+            b"hero_tree",
+            merkle_tree_key.as_ref(),
+            &[ctx.accounts.hero_tree.bump],
+        ];
+
+        let metadata = MetadataArgs {
+            name,
+            symbol,
+            uri,
+            seller_fee_basis_points,
+            primary_sale_happened: false,
+            is_mutable: true,
+            edition_nonce: None,
+            token_standard: Some(CnftTokenStandard::NonFungible),
+            collection: Some(Collection {
+                verified: false,
+                key: ctx.accounts.collection_mint.key(),
+            }),
+            uses: None,
+            token_program_version: TokenProgramVersion::Original,
+            creators: vec![Creator {
+                address: ctx.accounts.hero_tree.key(),
+                verified: false,
+                share: 100,
+            }],
+        };
+
+        let nonce = ctx.accounts.hero_tree.minted;
+
+        MintToCollectionV1CpiBuilder::new(&ctx.accounts.bubblegum_program.to_account_info())
+            .tree_config(&ctx.accounts.tree_config.to_account_info())
+            .leaf_owner(&ctx.accounts.leaf_owner.to_account_info())
+            .leaf_delegate(&ctx.accounts.leaf_owner.to_account_info())
+            .merkle_tree(&ctx.accounts.merkle_tree.to_account_info())
+            .payer(&ctx.accounts.admin.to_account_info())
+            .tree_creator_or_delegate(&ctx.accounts.hero_tree.to_account_info())
+            .collection_authority(&ctx.accounts.hero_tree.to_account_info())
+            .collection_authority_record_pda(Some(&ctx.accounts.collection_authority_record.to_account_info()))
+            .collection_mint(&ctx.accounts.collection_mint.to_account_info())
+            .collection_metadata(&ctx.accounts.collection_metadata.to_account_info())
+            .collection_edition(&ctx.accounts.collection_edition.to_account_info())
+            .bubblegum_signer(&ctx.accounts.bubblegum_signer.to_account_info())
+            .log_wrapper(&ctx.accounts.log_wrapper.to_account_info())
+            .compression_program(&ctx.accounts.compression_program.to_account_info())
+            .token_metadata_program(&ctx.accounts.token_metadata_program.to_account_info())
+            .system_program(&ctx.accounts.system_program.to_account_info())
+            .metadata(metadata)
+            .invoke_signed(&[signer_seeds])?;
+
+        let hero_tree = &mut ctx.accounts.hero_tree;
+        hero_tree.minted = hero_tree.minted.checked_add(1).ok_or(MarketplaceError::MathOverflow)?;
+
+        emit!(CompressedHeroMinted {
+            merkle_tree: merkle_tree_key,
+            asset_id: get_asset_id(&merkle_tree_key, nonce),
+            leaf_owner: ctx.accounts.leaf_owner.key(),
+            nonce,
+        });
+
+        Ok(())
+    }
+
+    pub fn list_cnft<'info>(ctx: Context<'_, '_, '_, 'info, ListCnft<'info>>, leaf: CnftLeaf, price: u64, use_sol: bool) -> Result<()> {
+        require!(
+            use_sol == (ctx.accounts.payment_mint.key() == native_mint::id()),
+            MarketplaceError::InvalidMint
+        );
+
+        let merkle_tree_key = ctx.accounts.bubblegum.merkle_tree.key();
+        let asset_id = get_asset_id(&merkle_tree_key, leaf.nonce);
+
+        DelegateCpiBuilder::new(&ctx.accounts.bubblegum.bubblegum_program.to_account_info())
+            .tree_config(&ctx.accounts.bubblegum.tree_config.to_account_info())
+            .leaf_owner(&ctx.accounts.seller.to_account_info())
+            .previous_leaf_delegate(&ctx.accounts.seller.to_account_info())
+            .new_leaf_delegate(&ctx.accounts.escrow_signer.to_account_info())
+            .merkle_tree(&ctx.accounts.bubblegum.merkle_tree.to_account_info())
+            .log_wrapper(&ctx.accounts.bubblegum.log_wrapper.to_account_info())
+            .compression_program(&ctx.accounts.bubblegum.compression_program.to_account_info())
+            .system_program(&ctx.accounts.system_program.to_account_info())
+            .root(leaf.root)
+            .data_hash(leaf.data_hash)
+            .creator_hash(leaf.creator_hash)
+            .nonce(leaf.nonce)
+            .index(leaf.index)
+            .add_remaining_accounts(&proof_accounts(ctx.remaining_accounts))
+            .invoke()?;
+
+        let listing = &mut ctx.accounts.listing;
+        listing.seller = ctx.accounts.seller.key();
+        listing.merkle_tree = merkle_tree_key;
+        listing.asset_id = asset_id;
+        listing.price = price;
+        listing.use_sol = use_sol;
+        listing.bump = *ctx.bumps.get("listing").unwrap();
+        listing.payment_mint = ctx.accounts.payment_mint.key();
+
+        emit!(CnftListed {
+            asset_id,
+            seller: listing.seller,
+            price,
+            use_sol,
+        });
+
+        Ok(())
+    }
+
+    pub fn buy_cnft<'info>(ctx: Context<'_, '_, '_, 'info, BuyCnft<'info>>, leaf: CnftLeaf) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        require!(
+            get_asset_id(&listing.merkle_tree, leaf.nonce) == listing.asset_id,
+            MarketplaceError::InvalidNFT
+        );
+
+        let trade_fee_bps = discounted_fee_bps(
+            ctx.accounts.market_config.trade_fee_bps,
+            ctx.accounts.buyer.key(),
+            ctx.accounts.fee_tier.as_ref(),
+            ctx.accounts.fee_tier_holding.as_ref(),
+            ctx.accounts.fee_tier_stake.as_ref(),
+        )?;
+        let trade_fee = listing
+            .price
+            .saturating_mul(trade_fee_bps as u64)
+            / 10_000;
+        let seller_amount = listing.price.saturating_sub(trade_fee);
+
+        let buyer_payment_info = ctx.accounts.buyer_payment_ata.as_ref().map(|a| a.to_account_info());
+        let seller_payment_info = ctx.accounts.seller_payment_ata.as_ref().map(|a| a.to_account_info());
+        let treasury_token_info = ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info());
+        let token_program_info = ctx.accounts.token_program.to_account_info();
+        let system_program_info = ctx.accounts.system_program.to_account_info();
+
+        // Grossed up so the treasury nets its fee under a Token-2022
+        // transfer fee
+        let trade_fee_sent = gross_up_transfer_fee(&ctx.accounts.payment_mint, trade_fee)?;
+
+        pay_fee(
+            listing.use_sol,
+            &ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.seller.to_account_info(),
+            buyer_payment_info.as_ref(),
+            seller_payment_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &token_program_info,
+            &system_program_info,
+            seller_amount,
+        )?;
+
+        pay_fee(
+            listing.use_sol,
+            &ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.treasury_pda.to_account_info(),
+            buyer_payment_info.as_ref(),
+            treasury_token_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &token_program_info,
+            &system_program_info,
+            trade_fee_sent,
+        )?;
+
+        let signer_seeds: &[&[u8]] = &[
+            b"escrow_signer",
+            listing.asset_id.as_ref(),
+            &[*ctx.bumps.get("escrow_signer").unwrap()],
+        ];
+
+        TransferCpiBuilder::new(&ctx.accounts.bubblegum.bubblegum_program.to_account_info())
+            .tree_config(&ctx.accounts.bubblegum.tree_config.to_account_info())
+            .leaf_owner(&ctx.accounts.seller.to_account_info(), false)
+            .leaf_delegate(&ctx.accounts.escrow_signer.to_account_info(), true)
+            .new_leaf_owner(&ctx.accounts.buyer.to_account_info())
+            .merkle_tree(&ctx.accounts.bubblegum.merkle_tree.to_account_info())
+            .log_wrapper(&ctx.accounts.bubblegum.log_wrapper.to_account_info())
+            .compression_program(&ctx.accounts.bubblegum.compression_program.to_account_info())
+            .system_program(&ctx.accounts.system_program.to_account_info())
+            .root(leaf.root)
+            .data_hash(leaf.data_hash)
+            .creator_hash(leaf.creator_hash)
+            .nonce(leaf.nonce)
+            .index(leaf.index)
+            .add_remaining_accounts(&proof_accounts(ctx.remaining_accounts))
+            .invoke_signed(&[signer_seeds])?;
+
+        emit!(CnftSold {
+            asset_id: listing.asset_id,
+            buyer: ctx.accounts.buyer.key(),
+            seller: listing.seller,
+            price: listing.price,
+            fee: trade_fee,
+            use_sol: listing.use_sol,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_cnft_listing<'info>(ctx: Context<'_, '_, '_, 'info, CancelCnftListing<'info>>, leaf: CnftLeaf) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        require!(
+            get_asset_id(&listing.merkle_tree, leaf.nonce) == listing.asset_id,
+            MarketplaceError::InvalidNFT
+        );
+
+        DelegateCpiBuilder::new(&ctx.accounts.bubblegum.bubblegum_program.to_account_info())
+            .tree_config(&ctx.accounts.bubblegum.tree_config.to_account_info())
+            .leaf_owner(&ctx.accounts.seller.to_account_info())
+            .previous_leaf_delegate(&ctx.accounts.escrow_signer.to_account_info())
+            .new_leaf_delegate(&ctx.accounts.seller.to_account_info())
+            .merkle_tree(&ctx.accounts.bubblegum.merkle_tree.to_account_info())
+            .log_wrapper(&ctx.accounts.bubblegum.log_wrapper.to_account_info())
+            .compression_program(&ctx.accounts.bubblegum.compression_program.to_account_info())
+            .system_program(&ctx.accounts.system_program.to_account_info())
+            .root(leaf.root)
+            .data_hash(leaf.data_hash)
+            .creator_hash(leaf.creator_hash)
+            .nonce(leaf.nonce)
+            .index(leaf.index)
+            .add_remaining_accounts(&proof_accounts(ctx.remaining_accounts))
+            .invoke()?;
+
+        emit!(CnftListingCancelled {
+            asset_id: listing.asset_id,
+            seller: listing.seller,
+        });
+
+        Ok(())
+    }
+
+    /// Closes a cNFT listing whose asset left the seller outside the
+    /// marketplace. The seller may always drop their own listing; anyone else
+    /// must prove they now own the leaf, which Bubblegum checks while
+    /// re-delegating it to themselves. Rent goes back to the seller.
+    pub fn close_stale_cnft_listing<'info>(ctx: Context<'_, '_, '_, 'info, CloseStaleCnftListing<'info>>, leaf: CnftLeaf) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        require!(
+            get_asset_id(&listing.merkle_tree, leaf.nonce) == listing.asset_id,
+            MarketplaceError::InvalidNFT
+        );
+
+        let closer = ctx.accounts.closer.key();
+        if closer != listing.seller {
+            DelegateCpiBuilder::new(&ctx.accounts.bubblegum.bubblegum_program.to_account_info())
+                .tree_config(&ctx.accounts.bubblegum.tree_config.to_account_info())
+                .leaf_owner(&ctx.accounts.closer.to_account_info())
+                .previous_leaf_delegate(&ctx.accounts.closer.to_account_info())
+                .new_leaf_delegate(&ctx.accounts.closer.to_account_info())
+                .merkle_tree(&ctx.accounts.bubblegum.merkle_tree.to_account_info())
+                .log_wrapper(&ctx.accounts.bubblegum.log_wrapper.to_account_info())
+                .compression_program(&ctx.accounts.bubblegum.compression_program.to_account_info())
+                .system_program(&ctx.accounts.system_program.to_account_info())
+                .root(leaf.root)
+                .data_hash(leaf.data_hash)
+                .creator_hash(leaf.creator_hash)
+                .nonce(leaf.nonce)
+                .index(leaf.index)
+                .add_remaining_accounts(&proof_accounts(ctx.remaining_accounts))
+                .invoke()?;
+        }
+
+        emit!(CnftListingCancelled {
+            asset_id: listing.asset_id,
+            seller: listing.seller,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_hero_mint_config(ctx: Context<SetHeroMintConfig>, collection_mint: Pubkey, payment_mint: Pubkey, mint_fee: u64) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+
+        let config = &mut ctx.accounts.hero_mint_config;
+        config.collection_mint = collection_mint;
+        config.payment_mint = payment_mint;
+        config.mint_fee = mint_fee;
+        config.collection_authority_bump = *ctx.bumps.get("collection_authority").unwrap();
+        config.bump = *ctx.bumps.get("hero_mint_config").unwrap();
+
+        emit!(HeroMintConfigUpdated {
+            collection_mint,
+            collection_authority: ctx.accounts.collection_authority.key(),
+            payment_mint,
+            mint_fee,
+        });
+
+        Ok(())
+    }
+
+    pub fn mint_hero(ctx: Context<MintHero>, name: String, symbol: String, uri: String, seller_fee_basis_points: u16) -> Result<()> {
+        let mint_key = ctx.accounts.mint.key();
+        let mint_auth_seeds: &[&[u8]] = &[
+            b"mint_auth",
+            mint_key.as_ref(),
+            &[*ctx.bumps.get("mint_authority").unwrap()],
+        ];
+
+        token_interface::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.owner_nft_ata.to_account_info(),
+                    authority: ctx.accounts.mint_authority.to_account_info(),
+                },
+                &[mint_auth_seeds],
+            ),
+            1,
+        )?;
+
+        if has_embedded_metadata(&ctx.accounts.mint)? {
+            fund_embedded_metadata_rent(
+                &ctx.accounts.owner.to_account_info(),
+                &ctx.accounts.mint.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                &name,
+                &symbol,
+                &uri,
+            )?;
+
+            invoke_signed(
+                &spl_token_metadata_interface::instruction::initialize(
+                    &token_2022::ID,
+                    &mint_key,
+                    &ctx.accounts.update_authority.key(),
+                    &mint_key,
+                    &ctx.accounts.mint_authority.key(),
+                    name.clone(),
+                    symbol.clone(),
+                    uri.clone(),
+                ),
+                &[
+                    ctx.accounts.mint.to_account_info(),
+                    ctx.accounts.update_authority.to_account_info(),
+                    ctx.accounts.mint_authority.to_account_info(),
+                ],
+                &[mint_auth_seeds],
+            )?;
+        } else {
+            let (expected_metadata, _) = MplMetadata::find_pda(&mint_key);
+            require_keys_eq!(
+                ctx.accounts.metadata.key(),
+                expected_metadata,
+                MarketplaceError::InvalidNFT
+            );
+
+            CreateMetadataAccountV3CpiBuilder::new(&ctx.accounts.token_metadata_program.to_account_info())
+                .metadata(&ctx.accounts.metadata.to_account_info())
+                .mint(&ctx.accounts.mint.to_account_info())
+                .mint_authority(&ctx.accounts.mint_authority.to_account_info())
+                .payer(&ctx.accounts.owner.to_account_info())
+                .update_authority(&ctx.accounts.update_authority.to_account_info(), false)
+                .system_program(&ctx.accounts.system_program.to_account_info())
+                .data(DataV2 {
+                    name,
+                    symbol,
+                    uri,
+                    seller_fee_basis_points,
+                    creators: None,
+                    collection: Some(mpl_token_metadata::types::Collection {
+                        verified: false,
+                        key: ctx.accounts.hero_collection.hero_mint_config.collection_mint,
+                    }),
+                    uses: None,
+                })
+                .is_mutable(true)
+                .invoke_signed(&[mint_auth_seeds])?;
+        }
+
+        ctx.accounts.hero_collection.add_hero(
+            &ctx.accounts.mint,
+            &ctx.accounts.mint_authority.to_account_info(),
+            mint_auth_seeds,
+            &ctx.accounts.metadata.to_account_info(),
+            &ctx.accounts.token_metadata_program.to_account_info(),
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        token_interface::set_authority(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                SetAuthority {
+                    current_authority: ctx.accounts.mint_authority.to_account_info(),
+                    account_or_mint: ctx.accounts.mint.to_account_info(),
+                },
+                &[mint_auth_seeds],
+            ),
+            AuthorityType::MintTokens,
+            None,
+        )?;
+
+        // Primary mints cost a fixed fee set by the admins, not a share of a
+        // caller-chosen price
+        let mint_fee = ctx.accounts.hero_collection.hero_mint_config.mint_fee;
+        let use_sol = ctx.accounts.payment_mint.key() == native_mint::id();
+        let mint_fee_sent = gross_up_transfer_fee(&ctx.accounts.payment_mint, mint_fee)?;
+
+        let owner_payment_info = ctx.accounts.owner_payment_ata.as_ref().map(|a| a.to_account_info());
+        let treasury_token_info = ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info());
+
+        pay_fee(
+            use_sol,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.treasury_pda.to_account_info(),
+            owner_payment_info.as_ref(),
+            treasury_token_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.payment_token_program.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            mint_fee_sent,
+        )?;
+
+        emit!(HeroMinted {
+            owner: ctx.accounts.owner.key(),
+            nft_mint: mint_key,
+            use_sol,
+            mint_fee,
+        });
+
+        Ok(())
+    }
+
+    pub fn list_nft(ctx: Context<ListNft>, price: u64, use_sol: bool, mode: ListingMode) -> Result<()> {
+        let collection = verified_collection(
+            &ctx.accounts.mint.to_account_info(),
+            ctx.accounts.pnft.metadata.as_ref().map(|m| m.as_ref()),
+        )?;
+        require!(
+            collection == Some(ctx.accounts.allowed_collection.collection_mint),
+            MarketplaceError::CollectionNotAllowed
+        );
+        require!(
+            use_sol == (ctx.accounts.payment_mint.key() == native_mint::id()),
+            MarketplaceError::InvalidMint
+        );
+
+        let relist_fee = price
+            .saturating_mul(ctx.accounts.market_config.relist_fee_bps as u64)
+            / 10_000;
+        let relist_fee_sent = gross_up_transfer_fee(&ctx.accounts.payment_mint, relist_fee)?;
+
+        let seller_payment_info = ctx.accounts.seller_payment_ata.as_ref().map(|a| a.to_account_info());
+        let treasury_token_info = ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info());
+
+        pay_fee(
+            use_sol,
+            &ctx.accounts.seller.to_account_info(),
+            &ctx.accounts.treasury_pda.to_account_info(),
+            seller_payment_info.as_ref(),
+            treasury_token_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.payment_token_program.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            relist_fee_sent,
+        )?;
+
+        let programmable = ctx.accounts.pnft.is_programmable(&ctx.accounts.mint.key())?;
+        match mode {
+            ListingMode::Delegate if programmable => {
+                ctx.accounts.pnft.delegate_transfer(
+                    &ctx.accounts.escrow_signer.to_account_info(),
+                    &ctx.accounts.mint.to_account_info(),
+                    &ctx.accounts.seller_nft_ata.to_account_info(),
+                    &ctx.accounts.seller.to_account_info(),
+                    &ctx.accounts.system_program.to_account_info(),
+                    &ctx.accounts.token_program.to_account_info(),
+                )?;
+            }
+            ListingMode::Delegate => {
+                token_interface::approve(
+                    CpiContext::new(
+                        ctx.accounts.token_program.to_account_info(),
+                        token_interface::Approve {
+                            to: ctx.accounts.seller_nft_ata.to_account_info(),
+                            delegate: ctx.accounts.escrow_signer.to_account_info(),
+                            authority: ctx.accounts.seller.to_account_info(),
+                        },
+                    ),
+                    1,
+                )?;
+            }
+            ListingMode::Custody => {
+                let escrow_vault = ctx
+                    .accounts
+                    .escrow_vault
+                    .as_ref()
+                    .ok_or(MarketplaceError::MissingEscrowVault)?
+                    .to_account_info();
+
+                if programmable {
+                    ctx.accounts.pnft.transfer(
+                        &ctx.accounts.seller_nft_ata.to_account_info(),
+                        &ctx.accounts.seller.to_account_info(),
+                        &escrow_vault,
+                        &ctx.accounts.escrow_signer.to_account_info(),
+                        &ctx.accounts.mint.to_account_info(),
+                        &ctx.accounts.seller.to_account_info(),
+                        &ctx.accounts.seller.to_account_info(),
+                        &ctx.accounts.system_program.to_account_info(),
+                        &ctx.accounts.token_program.to_account_info(),
+                        &[],
+                    )?;
+                } else {
+                    token_interface::transfer_checked(
+                        CpiContext::new(
+                            ctx.accounts.token_program.to_account_info(),
+                            TransferChecked {
+                                from: ctx.accounts.seller_nft_ata.to_account_info(),
+                                mint: ctx.accounts.mint.to_account_info(),
+                                to: escrow_vault,
+                                authority: ctx.accounts.seller.to_account_info(),
+                            },
+                        ),
+                        1,
+                        ctx.accounts.mint.decimals,
+                    )?;
+                }
+            }
+        }
+
+        let listing = &mut ctx.accounts.listing;
+        listing.seller = ctx.accounts.seller.key();
+        listing.nft_mint = ctx.accounts.mint.key();
+        listing.price = price;
+        listing.use_sol = use_sol;
+        listing.bump = *ctx.bumps.get("listing").unwrap();
+        listing.mode = mode;
+        listing.payment_mint = ctx.accounts.payment_mint.key();
+
+        emit!(NftListed {
+            seller: listing.seller,
+            nft_mint: listing.nft_mint,
+            collection: ctx.accounts.allowed_collection.collection_mint,
+            price,
+            use_sol,
+            listing_fee: relist_fee,
+            mode,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_listing(ctx: Context<CancelListing>) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        let programmable = ctx.accounts.pnft.is_programmable(&listing.nft_mint)?;
+
+        match listing.mode {
+            ListingMode::Delegate => {
+                if programmable {
+                    ctx.accounts.pnft.revoke_transfer(
+                        &ctx.accounts.escrow_signer.to_account_info(),
+                        &ctx.accounts.mint.to_account_info(),
+                        &ctx.accounts.seller_nft_ata.to_account_info(),
+                        &ctx.accounts.seller.to_account_info(),
+                        &ctx.accounts.system_program.to_account_info(),
+                        &ctx.accounts.token_program.to_account_info(),
+                    )?;
+                } else {
+                    token_interface::revoke(CpiContext::new(
+                        ctx.accounts.token_program.to_account_info(),
+                        token_interface::Revoke {
+                            source: ctx.accounts.seller_nft_ata.to_account_info(),
+                            authority: ctx.accounts.seller.to_account_info(),
+                        },
+                    ))?;
+                }
+            }
+            ListingMode::Custody => {
+                let escrow_vault = ctx
+                    .accounts
+                    .escrow_vault
+                    .as_ref()
+                    .ok_or(MarketplaceError::MissingEscrowVault)?
+                    .to_account_info();
+                let signer_seeds: &[&[u8]] = &[
+                    b"escrow_signer",
+                    listing.nft_mint.as_ref(),
+                    &[*ctx.bumps.get("escrow_signer").unwrap()],
+                ];
+
+                if programmable {
+                    ctx.accounts.pnft.transfer(
+                        &escrow_vault,
+                        &ctx.accounts.escrow_signer.to_account_info(),
+                        &ctx.accounts.seller_nft_ata.to_account_info(),
+                        &ctx.accounts.seller.to_account_info(),
+                        &ctx.accounts.mint.to_account_info(),
+                        &ctx.accounts.escrow_signer.to_account_info(),
+                        &ctx.accounts.seller.to_account_info(),
+                        &ctx.accounts.system_program.to_account_info(),
+                        &ctx.accounts.token_program.to_account_info(),
+                        &[signer_seeds],
+                    )?;
+                } else {
+                    token_interface::transfer_checked(
+                        CpiContext::new_with_signer(
+                            ctx.accounts.token_program.to_account_info(),
+                            TransferChecked {
+                                from: escrow_vault.clone(),
+                                mint: ctx.accounts.mint.to_account_info(),
+                                to: ctx.accounts.seller_nft_ata.to_account_info(),
+                                authority: ctx.accounts.escrow_signer.to_account_info(),
+                            },
+                            &[signer_seeds],
+                        ),
+                        1,
+                        ctx.accounts.mint.decimals,
+                    )?;
+
+                    token_interface::close_account(CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        token_interface::CloseAccount {
+                            account: escrow_vault,
+                            destination: ctx.accounts.seller.to_account_info(),
+                            authority: ctx.accounts.escrow_signer.to_account_info(),
+                        },
+                        &[signer_seeds],
+                    ))?;
+                }
+            }
+        }
+
+        emit!(ListingCancelled {
+            nft_mint: listing.nft_mint,
+            seller: listing.seller,
+            mode: listing.mode,
+        });
+
+        Ok(())
+    }
+
+    pub fn upgrade_listing(ctx: Context<UpgradeListing>) -> Result<()> {
+        let listing_info = ctx.accounts.listing.to_account_info();
+        require_keys_eq!(*listing_info.owner, crate::ID, MarketplaceError::InvalidNFT);
+        if listing_info.data_len() >= LISTING_SPACE {
+            return Ok(());
+        }
+
+        // seller @ 8, use_sol @ 80 are shared by every listing layout
+        let (seller, use_sol) = {
+            let data = listing_info.try_borrow_data()?;
+            require!(
+                data.len() >= 81 && data[..8] == Listing::DISCRIMINATOR,
+                MarketplaceError::InvalidNFT
+            );
+            (Pubkey::try_from(&data[8..40]).unwrap(), data[80] != 0)
+        };
+
+        // SOL listings are unambiguous; for SPL listings only the seller can
+        // say which mint the price was quoted in.
+        let payment_mint = if use_sol {
+            native_mint::id()
+        } else {
+            require_keys_eq!(ctx.accounts.payer.key(), seller, MarketplaceError::Unauthorized);
+            ctx.accounts
+                .payment_mint
+                .as_ref()
+                .ok_or(MarketplaceError::InvalidMint)?
+                .key()
+        };
+
+        let rent_due = Rent::get()?
+            .minimum_balance(LISTING_SPACE)
+            .saturating_sub(listing_info.lamports());
+        if rent_due > 0 {
+            invoke(
+                &system_instruction::transfer(
+                    &ctx.accounts.payer.key(),
+                    &listing_info.key(),
+                    rent_due,
+                ),
+                &[
+                    ctx.accounts.payer.to_account_info(),
+                    listing_info.clone(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        // Pre-custody listings are all delegate mode, which serializes as 0.
+        listing_info.realloc(LISTING_SPACE, true)?;
+        listing_info.try_borrow_mut_data()?[LISTING_SPACE - 32..].copy_from_slice(payment_mint.as_ref());
+
+        Ok(())
+    }
+
+    /// Remaining accounts, per mint: mint, seller token account, escrow
+    /// signer, single-listing PDA (must not exist), metadata (any account for
+    /// Token-2022 group members) and the collection's `AllowedCollection`.
+    pub fn list_bundle<'info>(ctx: Context<'_, '_, '_, 'info, ListBundle<'info>>, bundle_id: u64, price: u64, use_sol: bool) -> Result<()> {
+        require!(
+            use_sol == (ctx.accounts.payment_mint.key() == native_mint::id()),
+            MarketplaceError::InvalidMint
+        );
+
+        let accounts = ctx.remaining_accounts;
+        require!(
+            !accounts.is_empty() && accounts.len() % 6 == 0,
+            MarketplaceError::InvalidBundle
+        );
+        let count = accounts.len() / 6;
+        require!(count <= MAX_BUNDLE_SIZE, MarketplaceError::InvalidBundle);
+
+        let mut mints = Vec::with_capacity(count);
+        for chunk in accounts.chunks(6) {
+            let (mint_info, seller_nft_info, escrow_info) = (&chunk[0], &chunk[1], &chunk[2]);
+            let (listing_info, metadata_info, allowed_info) = (&chunk[3], &chunk[4], &chunk[5]);
+
+            let seller_nft = InterfaceAccount::<TokenAccount>::try_from(seller_nft_info)?;
+            require_keys_eq!(seller_nft.mint, mint_info.key(), MarketplaceError::InvalidNFT);
+            require_keys_eq!(seller_nft.owner, ctx.accounts.seller.key(), MarketplaceError::InvalidOwner);
+            require!(seller_nft.amount == 1, MarketplaceError::InvalidNFT);
+            require!(!mints.contains(&mint_info.key()), MarketplaceError::InvalidBundle);
+
+            let (expected_escrow, _) = Pubkey::find_program_address(
+                &[b"escrow_signer", mint_info.key().as_ref()],
+                ctx.program_id,
+            );
+            require_keys_eq!(escrow_info.key(), expected_escrow, MarketplaceError::InvalidBundle);
+
+            // A single listing shares the escrow delegate, which cancel_bundle revokes
+            let (expected_listing, _) = Pubkey::find_program_address(
+                &[b"listing", mint_info.key().as_ref()],
+                ctx.program_id,
+            );
+            require_keys_eq!(listing_info.key(), expected_listing, MarketplaceError::InvalidBundle);
+            require!(listing_info.data_is_empty(), MarketplaceError::AlreadyListed);
+
+            let collection = verified_collection(mint_info, Some(metadata_info))?
+                .ok_or(MarketplaceError::CollectionNotAllowed)?;
+            let (expected_allowed, _) = Pubkey::find_program_address(
+                &[b"allowed_collection", collection.as_ref()],
+                ctx.program_id,
+            );
+            require!(
+                allowed_info.key() == expected_allowed
+                    && *allowed_info.owner == crate::ID
+                    && !allowed_info.data_is_empty(),
+                MarketplaceError::CollectionNotAllowed
+            );
+
+            token_interface::approve(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::Approve {
+                        to: seller_nft_info.clone(),
+                        delegate: escrow_info.clone(),
+                        authority: ctx.accounts.seller.to_account_info(),
+                    },
+                ),
+                1,
+            )?;
+
+            mints.push(mint_info.key());
+        }
+
+        let listing_fee = price
+            .saturating_mul(ctx.accounts.market_config.relist_fee_bps as u64)
+            / 10_000;
+        let listing_fee_sent = gross_up_transfer_fee(&ctx.accounts.payment_mint, listing_fee)?;
+
+        let seller_payment_info = ctx.accounts.seller_payment_ata.as_ref().map(|a| a.to_account_info());
+        let treasury_token_info = ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info());
+
+        pay_fee(
+            use_sol,
+            &ctx.accounts.seller.to_account_info(),
+            &ctx.accounts.treasury_pda.to_account_info(),
+            seller_payment_info.as_ref(),
+            treasury_token_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.payment_token_program.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            listing_fee_sent,
+        )?;
+
+        let bundle = &mut ctx.accounts.bundle;
+        bundle.seller = ctx.accounts.seller.key();
+        bundle.bundle_id = bundle_id;
+        bundle.mints = mints;
+        bundle.price = price;
+        bundle.use_sol = use_sol;
+        bundle.bump = *ctx.bumps.get("bundle").unwrap();
+        bundle.payment_mint = ctx.accounts.payment_mint.key();
+
+        emit!(BundleListed {
+            bundle: bundle.key(),
+            seller: bundle.seller,
+            mints: bundle.mints.clone(),
+            price,
+            use_sol,
+            listing_fee,
+        });
+
+        Ok(())
+    }
+
+    pub fn buy_bundle<'info>(ctx: Context<'_, '_, '_, 'info, BuyBundle<'info>>) -> Result<()> {
+        let bundle = &ctx.accounts.bundle;
+        let accounts = ctx.remaining_accounts;
+        require!(
+            accounts.len() == bundle.mints.len() * 4,
+            MarketplaceError::InvalidBundle
+        );
+
+        let trade_fee_bps = discounted_fee_bps(
+            ctx.accounts.market_config.trade_fee_bps,
+            ctx.accounts.buyer.key(),
+            ctx.accounts.fee_tier.as_ref(),
+            ctx.accounts.fee_tier_holding.as_ref(),
+            ctx.accounts.fee_tier_stake.as_ref(),
+        )?;
+        let trade_fee = bundle
+            .price
+            .saturating_mul(trade_fee_bps as u64)
+            / 10_000;
+        let seller_amount = bundle.price.saturating_sub(trade_fee);
+
+        let buyer_payment_info = ctx.accounts.buyer_payment_ata.as_ref().map(|a| a.to_account_info());
+        let seller_payment_info = ctx.accounts.seller_payment_ata.as_ref().map(|a| a.to_account_info());
+        let treasury_token_info = ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info());
+        let token_program_info = ctx.accounts.token_program.to_account_info();
+        let system_program_info = ctx.accounts.system_program.to_account_info();
+
+        let trade_fee_sent = gross_up_transfer_fee(&ctx.accounts.payment_mint, trade_fee)?;
+
+        pay_fee(
+            bundle.use_sol,
+            &ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.seller.to_account_info(),
+            buyer_payment_info.as_ref(),
+            seller_payment_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &token_program_info,
+            &system_program_info,
+            seller_amount,
+        )?;
+
+        pay_fee(
+            bundle.use_sol,
+            &ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.treasury_pda.to_account_info(),
+            buyer_payment_info.as_ref(),
+            treasury_token_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &token_program_info,
+            &system_program_info,
+            trade_fee_sent,
+        )?;
+
+        for (chunk, nft_mint) in accounts.chunks(4).zip(bundle.mints.iter()) {
+            let (mint_info, seller_nft_info, escrow_info, buyer_nft_info) =
+                (&chunk[0], &chunk[1], &chunk[2], &chunk[3]);
+            require_keys_eq!(mint_info.key(), *nft_mint, MarketplaceError::InvalidBundle);
+
+            let mint = InterfaceAccount::<Mint>::try_from(mint_info)?;
+            let seller_nft = InterfaceAccount::<TokenAccount>::try_from(seller_nft_info)?;
+            require_keys_eq!(seller_nft.mint, *nft_mint, MarketplaceError::InvalidNFT);
+            require_keys_eq!(seller_nft.owner, bundle.seller, MarketplaceError::InvalidOwner);
+            let buyer_nft = InterfaceAccount::<TokenAccount>::try_from(buyer_nft_info)?;
+            require_keys_eq!(buyer_nft.mint, *nft_mint, MarketplaceError::InvalidNFT);
+            require_keys_eq!(buyer_nft.owner, ctx.accounts.buyer.key(), MarketplaceError::InvalidOwner);
+
+            let (expected_escrow, escrow_bump) = Pubkey::find_program_address(
+                &[b"escrow_signer", nft_mint.as_ref()],
+                ctx.program_id,
+            );
+            require_keys_eq!(escrow_info.key(), expected_escrow, MarketplaceError::InvalidBundle);
+
+            let signer_seeds: &[&[u8]] = &[
+                b"escrow_signer",
+                nft_mint.as_ref(),
+                &[escrow_bump],
+            ];
+
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: seller_nft_info.clone(),
+                        mint: mint_info.clone(),
+                        to: buyer_nft_info.clone(),
+                        authority: escrow_info.clone(),
+                    },
+                    &[signer_seeds],
+                ),
+                1,
+                mint.decimals,
+            )?;
+        }
+
+        emit!(BundleSold {
+            bundle: bundle.key(),
+            buyer: ctx.accounts.buyer.key(),
+            seller: bundle.seller,
+            mints: bundle.mints.clone(),
+            price: bundle.price,
+            fee: trade_fee,
+            use_sol: bundle.use_sol,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_bundle<'info>(ctx: Context<'_, '_, '_, 'info, CancelBundle<'info>>) -> Result<()> {
+        let bundle = &ctx.accounts.bundle;
+
+        for seller_nft_info in ctx.remaining_accounts {
+            let seller_nft = InterfaceAccount::<TokenAccount>::try_from(seller_nft_info)?;
+            require!(bundle.mints.contains(&seller_nft.mint), MarketplaceError::InvalidBundle);
+            require_keys_eq!(seller_nft.owner, bundle.seller, MarketplaceError::InvalidOwner);
+
+            token_interface::revoke(CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::Revoke {
+                    source: seller_nft_info.clone(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                },
+            ))?;
+        }
+
+        emit!(BundleCancelled {
+            bundle: bundle.key(),
+            seller: bundle.seller,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_trade_offer<'info>(ctx: Context<'_, '_, '_, 'info, CreateTradeOffer<'info>>, offer_id: u64, wanted_mints: Vec<Pubkey>, taker: Option<Pubkey>, sol_amount: u64, token_amount: u64) -> Result<()> {
+        let accounts = ctx.remaining_accounts;
+        require!(accounts.len() % 3 == 0, MarketplaceError::InvalidTradeOffer);
+        let offered_count = accounts.len() / 3;
+        require!(
+            offered_count <= MAX_TRADE_OFFER_MINTS && wanted_mints.len() <= MAX_TRADE_OFFER_MINTS,
+            MarketplaceError::InvalidTradeOffer
+        );
+        require!(
+            offered_count > 0 || sol_amount > 0 || token_amount > 0,
+            MarketplaceError::InvalidTradeOffer
+        );
+        require!(!wanted_mints.is_empty(), MarketplaceError::InvalidTradeOffer);
+
+        let offer_key = ctx.accounts.offer.key();
+        let mut offered_mints = Vec::with_capacity(offered_count);
+        for chunk in accounts.chunks(3) {
+            let (mint_info, maker_nft_info, vault_info) = (&chunk[0], &chunk[1], &chunk[2]);
+
+            let vault = InterfaceAccount::<TokenAccount>::try_from(vault_info)?;
+            require_keys_eq!(vault.mint, mint_info.key(), MarketplaceError::InvalidTradeOffer);
+            require_keys_eq!(vault.owner, offer_key, MarketplaceError::InvalidTradeOffer);
+            require!(!offered_mints.contains(&mint_info.key()), MarketplaceError::InvalidTradeOffer);
+
+            let mint = InterfaceAccount::<Mint>::try_from(mint_info)?;
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: maker_nft_info.clone(),
+                        mint: mint_info.clone(),
+                        to: vault_info.clone(),
+                        authority: ctx.accounts.maker.to_account_info(),
+                    },
+                ),
+                1,
+                mint.decimals,
+            )?;
+
+            offered_mints.push(mint_info.key());
+        }
+
+        if sol_amount > 0 {
+            invoke(
+                &system_instruction::transfer(
+                    &ctx.accounts.maker.key(),
+                    &offer_key,
+                    sol_amount,
+                ),
+                &[
+                    ctx.accounts.maker.to_account_info(),
+                    ctx.accounts.offer.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        let mut token_mint = None;
+        let mut token_received = 0;
+        if token_amount > 0 {
+            let mint = ctx
+                .accounts
+                .token_mint
+                .as_ref()
+                .ok_or(MarketplaceError::InvalidTradeOffer)?;
+            let maker_token = ctx
+                .accounts
+                .maker_token_account
+                .as_ref()
+                .ok_or(MarketplaceError::InvalidTradeOffer)?;
+            let offer_token_vault = ctx
+                .accounts
+                .offer_token_vault
+                .as_ref()
+                .ok_or(MarketplaceError::InvalidTradeOffer)?;
+
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: maker_token.to_account_info(),
+                        mint: mint.to_account_info(),
+                        to: offer_token_vault.to_account_info(),
+                        authority: ctx.accounts.maker.to_account_info(),
+                    },
+                ),
+                token_amount,
+                mint.decimals,
+            )?;
+            token_mint = Some(mint.key());
+            token_received = token_amount
+                .checked_sub(transfer_fee_for(mint, token_amount)?)
+                .ok_or(MarketplaceError::MathOverflow)?;
+        }
+
+        let offer = &mut ctx.accounts.offer;
+        offer.maker = ctx.accounts.maker.key();
+        offer.offer_id = offer_id;
+        offer.taker = taker;
+        offer.offered_mints = offered_mints;
+        offer.wanted_mints = wanted_mints;
+        offer.sol_amount = sol_amount;
+        offer.token_mint = token_mint;
+        offer.token_amount = token_received;
+        offer.bump = *ctx.bumps.get("offer").unwrap();
+
+        emit!(TradeOfferCreated {
+            offer: offer_key,
+            maker: offer.maker,
+            taker,
+            offered_mints: offer.offered_mints.clone(),
+            wanted_mints: offer.wanted_mints.clone(),
+            sol_amount,
+            token_mint,
+            token_amount: token_received,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_trade_offer<'info>(ctx: Context<'_, '_, '_, 'info, AcceptTradeOffer<'info>>) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        if let Some(taker) = offer.taker {
+            require_keys_eq!(taker, ctx.accounts.taker.key(), MarketplaceError::Unauthorized);
+        }
+
+        let offered_len = offer.offered_mints.len() * 3;
+        let accounts = ctx.remaining_accounts;
+        require!(
+            accounts.len() == offered_len + offer.wanted_mints.len() * 3,
+            MarketplaceError::InvalidTradeOffer
+        );
+        let (offered_accounts, wanted_accounts) = accounts.split_at(offered_len);
+
+        let fee = ctx.accounts.market_config.trade_offer_fee_lamports;
+        if fee > 0 {
+            invoke(
+                &system_instruction::transfer(
+                    &ctx.accounts.taker.key(),
+                    &ctx.accounts.treasury_pda.key(),
+                    fee,
+                ),
+                &[
+                    ctx.accounts.taker.to_account_info(),
+                    ctx.accounts.treasury_pda.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        for (chunk, wanted_mint) in wanted_accounts.chunks(3).zip(offer.wanted_mints.iter()) {
+            let (mint_info, taker_nft_info, maker_nft_info) = (&chunk[0], &chunk[1], &chunk[2]);
+            require_keys_eq!(mint_info.key(), *wanted_mint, MarketplaceError::InvalidTradeOffer);
+
+            let maker_nft = InterfaceAccount::<TokenAccount>::try_from(maker_nft_info)?;
+            require_keys_eq!(maker_nft.mint, *wanted_mint, MarketplaceError::InvalidTradeOffer);
+            require_keys_eq!(maker_nft.owner, offer.maker, MarketplaceError::InvalidOwner);
+
+            let mint = InterfaceAccount::<Mint>::try_from(mint_info)?;
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: taker_nft_info.clone(),
+                        mint: mint_info.clone(),
+                        to: maker_nft_info.clone(),
+                        authority: ctx.accounts.taker.to_account_info(),
+                    },
+                ),
+                1,
+                mint.decimals,
+            )?;
+        }
+
+        let maker_key = offer.maker;
+        let offer_id_bytes = offer.offer_id.to_le_bytes();
+        let signer_seeds: &[&[u8]] = &[
+            b"trade_offer",
+            maker_key.as_ref(),
+            &offer_id_bytes,
+            &[offer.bump],
+        ];
+
+        for (chunk, offered_mint) in offered_accounts.chunks(3).zip(offer.offered_mints.iter()) {
+            let (mint_info, vault_info, taker_nft_info) = (&chunk[0], &chunk[1], &chunk[2]);
+            require_keys_eq!(mint_info.key(), *offered_mint, MarketplaceError::InvalidTradeOffer);
+
+            let taker_nft = InterfaceAccount::<TokenAccount>::try_from(taker_nft_info)?;
+            require_keys_eq!(taker_nft.owner, ctx.accounts.taker.key(), MarketplaceError::InvalidOwner);
+
+            release_from_vault(
+                &ctx.accounts.token_program.to_account_info(),
+                mint_info,
+                vault_info,
+                taker_nft_info,
+                &ctx.accounts.offer.to_account_info(),
+                &ctx.accounts.maker.to_account_info(),
+                &[signer_seeds],
+                1,
+            )?;
+        }
+
+        if offer.token_amount > 0 {
+            let mint = ctx
+                .accounts
+                .token_mint
+                .as_ref()
+                .ok_or(MarketplaceError::InvalidTradeOffer)?;
+            let offer_token_vault = ctx
+                .accounts
+                .offer_token_vault
+                .as_ref()
+                .ok_or(MarketplaceError::InvalidTradeOffer)?;
+            let taker_token = ctx
+                .accounts
+                .taker_token_account
+                .as_ref()
+                .ok_or(MarketplaceError::InvalidTradeOffer)?;
+
+            release_from_vault(
+                &ctx.accounts.token_program.to_account_info(),
+                &mint.to_account_info(),
+                &offer_token_vault.to_account_info(),
+                &taker_token.to_account_info(),
+                &ctx.accounts.offer.to_account_info(),
+                &ctx.accounts.maker.to_account_info(),
+                &[signer_seeds],
+                offer.token_amount,
+            )?;
+        }
+
+        if offer.sol_amount > 0 {
+            let offer_info = ctx.accounts.offer.to_account_info();
+            let taker_info = ctx.accounts.taker.to_account_info();
+            **offer_info.try_borrow_mut_lamports()? -= offer.sol_amount;
+            **taker_info.try_borrow_mut_lamports()? += offer.sol_amount;
+        }
+
+        emit!(TradeOfferAccepted {
+            offer: ctx.accounts.offer.key(),
+            maker: maker_key,
+            taker: ctx.accounts.taker.key(),
+            fee,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_trade_offer<'info>(ctx: Context<'_, '_, '_, 'info, CancelTradeOffer<'info>>) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        let accounts = ctx.remaining_accounts;
+        require!(
+            accounts.len() == offer.offered_mints.len() * 3,
+            MarketplaceError::InvalidTradeOffer
+        );
+
+        let maker_key = offer.maker;
+        let offer_id_bytes = offer.offer_id.to_le_bytes();
+        let signer_seeds: &[&[u8]] = &[
+            b"trade_offer",
+            maker_key.as_ref(),
+            &offer_id_bytes,
+            &[offer.bump],
+        ];
+
+        for (chunk, offered_mint) in accounts.chunks(3).zip(offer.offered_mints.iter()) {
+            let (mint_info, vault_info, maker_nft_info) = (&chunk[0], &chunk[1], &chunk[2]);
+            require_keys_eq!(mint_info.key(), *offered_mint, MarketplaceError::InvalidTradeOffer);
+
+            release_from_vault(
+                &ctx.accounts.token_program.to_account_info(),
+                mint_info,
+                vault_info,
+                maker_nft_info,
+                &ctx.accounts.offer.to_account_info(),
+                &ctx.accounts.maker.to_account_info(),
+                &[signer_seeds],
+                1,
+            )?;
+        }
+
+        if offer.token_amount > 0 {
+            let mint = ctx
+                .accounts
+                .token_mint
+                .as_ref()
+                .ok_or(MarketplaceError::InvalidTradeOffer)?;
+            let offer_token_vault = ctx
+                .accounts
+                .offer_token_vault
+                .as_ref()
+                .ok_or(MarketplaceError::InvalidTradeOffer)?;
+            let maker_token = ctx
+                .accounts
+                .maker_token_account
+                .as_ref()
+                .ok_or(MarketplaceError::InvalidTradeOffer)?;
+
+            release_from_vault(
+                &ctx.accounts.token_program.to_account_info(),
+                &mint.to_account_info(),
+                &offer_token_vault.to_account_info(),
+                &maker_token.to_account_info(),
+                &ctx.accounts.offer.to_account_info(),
+                &ctx.accounts.maker.to_account_info(),
+                &[signer_seeds],
+                offer.token_amount,
+            )?;
+        }
+
+        emit!(TradeOfferCancelled {
+            offer: ctx.accounts.offer.key(),
+            maker: maker_key,
+        });
+
+        Ok(())
+    }
+
+    pub fn list_for_rent(ctx: Context<ListForRent>, daily_price: u64, max_days: u16, use_sol: bool) -> Result<()> {
+        require!(max_days > 0, MarketplaceError::InvalidRentalDuration);
+        require!(
+            use_sol == (ctx.accounts.payment_mint.key() == native_mint::id()),
+            MarketplaceError::InvalidMint
+        );
+
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.owner_nft_ata.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.rental_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            1,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        let rental = &mut ctx.accounts.rental;
+        rental.owner = ctx.accounts.owner.key();
+        rental.nft_mint = ctx.accounts.mint.key();
+        rental.daily_price = daily_price;
+        rental.max_days = max_days;
+        rental.use_sol = use_sol;
+        rental.renter = None;
+        rental.rented_until = 0;
+        rental.bump = *ctx.bumps.get("rental").unwrap();
+        rental.payment_mint = ctx.accounts.payment_mint.key();
+
+        emit!(HeroListedForRent {
+            nft_mint: rental.nft_mint,
+            owner: rental.owner,
+            daily_price,
+            max_days,
+            use_sol,
+        });
+
+        Ok(())
+    }
+
+    pub fn rent_hero(ctx: Context<RentHero>, days: u16) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let rental = &ctx.accounts.rental;
+        require!(days > 0 && days <= rental.max_days, MarketplaceError::InvalidRentalDuration);
+        require!(rental.rented_until <= now, MarketplaceError::RentalActive);
+
+        let total = rental
+            .daily_price
+            .checked_mul(days as u64)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        let trade_fee_bps = discounted_fee_bps(
+            ctx.accounts.market_config.trade_fee_bps,
+            ctx.accounts.renter.key(),
+            ctx.accounts.fee_tier.as_ref(),
+            ctx.accounts.fee_tier_holding.as_ref(),
+            ctx.accounts.fee_tier_stake.as_ref(),
+        )?;
+        let fee = total.saturating_mul(trade_fee_bps as u64) / 10_000;
+        let owner_amount = total.saturating_sub(fee);
+
+        let renter_payment_info = ctx.accounts.renter_payment_ata.as_ref().map(|a| a.to_account_info());
+        let owner_payment_info = ctx.accounts.owner_payment_ata.as_ref().map(|a| a.to_account_info());
+        let treasury_token_info = ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info());
+        let token_program_info = ctx.accounts.payment_token_program.to_account_info();
+        let system_program_info = ctx.accounts.system_program.to_account_info();
+
+        let fee_sent = gross_up_transfer_fee(&ctx.accounts.payment_mint, fee)?;
+
+        pay_fee(
+            rental.use_sol,
+            &ctx.accounts.renter.to_account_info(),
+            &ctx.accounts.owner.to_account_info(),
+            renter_payment_info.as_ref(),
+            owner_payment_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &token_program_info,
+            &system_program_info,
+            owner_amount,
+        )?;
+
+        pay_fee(
+            rental.use_sol,
+            &ctx.accounts.renter.to_account_info(),
+            &ctx.accounts.treasury_pda.to_account_info(),
+            renter_payment_info.as_ref(),
+            treasury_token_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &token_program_info,
+            &system_program_info,
+            fee_sent,
+        )?;
+
+        let rental = &mut ctx.accounts.rental;
+        rental.renter = Some(ctx.accounts.renter.key());
+        rental.rented_until = now
+            .checked_add(days as i64 * SECONDS_PER_DAY)
+            .ok_or(MarketplaceError::MathOverflow)?;
+
+        emit!(HeroRented {
+            nft_mint: rental.nft_mint,
+            owner: rental.owner,
+            renter: ctx.accounts.renter.key(),
+            days,
+            price: total,
+            fee,
+            rented_until: rental.rented_until,
+        });
+
+        Ok(())
+    }
+
+    pub fn reclaim_rental(ctx: Context<ReclaimRental>) -> Result<()> {
+        let rental = &ctx.accounts.rental;
+        require!(
+            rental.rented_until <= Clock::get()?.unix_timestamp,
+            MarketplaceError::RentalActive
+        );
+
+        let signer_seeds: &[&[u8]] = &[
+            b"rental",
+            rental.nft_mint.as_ref(),
+            &[rental.bump],
+        ];
+
+        release_from_vault(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.mint.to_account_info(),
+            &ctx.accounts.rental_vault.to_account_info(),
+            &ctx.accounts.owner_nft_ata.to_account_info(),
+            &ctx.accounts.rental.to_account_info(),
+            &ctx.accounts.owner.to_account_info(),
+            &[signer_seeds],
+            1,
+        )?;
+
+        emit!(RentalReclaimed {
+            nft_mint: rental.nft_mint,
+            owner: rental.owner,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_hero_rarity(ctx: Context<SetHeroRarity>, nft_mint: Pubkey, rarity: Rarity) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+
+        let entry = &mut ctx.accounts.hero_rarity;
+        entry.nft_mint = nft_mint;
+        entry.rarity = rarity;
+        entry.bump = *ctx.bumps.get("hero_rarity").unwrap();
+
+        emit!(HeroRaritySet { nft_mint, rarity });
+
+        Ok(())
+    }
+
+    pub fn create_hero_staking_pool(ctx: Context<CreateHeroStakingPool>, reward_rate_per_second: u64, rarity_weights: [u16; 4]) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+        require!(
+            rarity_weights.iter().all(|w| *w > 0),
+            MarketplaceError::InvalidStakingConfig
+        );
+
+        let pool = &mut ctx.accounts.pool;
+        pool.collection_mint = ctx.accounts.collection_mint.key();
+        pool.reward_mint = ctx.accounts.reward_mint.key();
+        pool.reward_vault = ctx.accounts.reward_vault.key();
+        pool.reward_rate_per_second = reward_rate_per_second;
+        pool.rarity_weights = rarity_weights;
+        pool.total_weight = 0;
+        pool.acc_reward_per_weight = 0;
+        pool.last_update_ts = Clock::get()?.unix_timestamp;
+        pool.bump = *ctx.bumps.get("pool").unwrap();
+
+        emit!(HeroStakingPoolCreated {
+            pool: pool.key(),
+            collection_mint: pool.collection_mint,
+            reward_mint: pool.reward_mint,
+            reward_rate_per_second,
+        });
+
+        Ok(())
+    }
+
+    pub fn stake_hero(ctx: Context<StakeHero>) -> Result<()> {
+        let collection = verified_collection(
+            &ctx.accounts.mint.to_account_info(),
+            ctx.accounts.metadata.as_ref().map(|m| m.as_ref()),
+        )?;
+        require!(
+            collection == Some(ctx.accounts.pool.collection_mint),
+            MarketplaceError::CollectionNotAllowed
+        );
+
+        // The registry entry wins whenever it exists; metadata is only
+        // consulted for heroes the admins never registered
+        let rarity_info = ctx.accounts.hero_rarity.to_account_info();
+        let rarity = if rarity_info.data_is_empty() {
+            rarity_from_metadata(&ctx.accounts.mint, &ctx.accounts.market_config.admin)?.unwrap_or(Rarity::Common)
+        } else {
+            Account::<HeroRarity>::try_from(&rarity_info)?.rarity
+        };
+
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.owner_nft_ata.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.stake_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            1,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.update_rewards(Clock::get()?.unix_timestamp)?;
+        let weight = pool.rarity_weights[rarity as usize] as u64;
+        pool.total_weight = pool.total_weight.checked_add(weight).ok_or(MarketplaceError::MathOverflow)?;
+
+        let stake = &mut ctx.accounts.stake;
+        stake.pool = pool.key();
+        stake.owner = ctx.accounts.owner.key();
+        stake.nft_mint = ctx.accounts.mint.key();
+        stake.weight = weight;
+        stake.reward_debt = pool.accrued_for(weight);
+        stake.unpaid_rewards = 0;
+        stake.staked_at = pool.last_update_ts;
+        stake.bump = *ctx.bumps.get("stake").unwrap();
+
+        emit!(HeroStaked {
+            pool: stake.pool,
+            owner: stake.owner,
+            nft_mint: stake.nft_mint,
+            rarity,
+            weight,
+        });
+
+        Ok(())
+    }
+
+    pub fn claim_hero_rewards(ctx: Context<ClaimHeroRewards>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.update_rewards(Clock::get()?.unix_timestamp)?;
+
+        let stake = &mut ctx.accounts.stake;
+        let accrued = pool.accrued_for(stake.weight);
+        let pending = (accrued.saturating_sub(stake.reward_debt) as u64)
+            .saturating_add(stake.unpaid_rewards);
+        let paid = pending.min(ctx.accounts.reward_vault.amount);
+        stake.reward_debt = accrued;
+        stake.unpaid_rewards = pending - paid;
+
+        let collection_mint = pool.collection_mint;
+        let signer_seeds: &[&[u8]] = &[
+            b"hero_staking_pool",
+            collection_mint.as_ref(),
+            &[pool.bump],
+        ];
+
+        if paid > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.reward_token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.reward_vault.to_account_info(),
+                        mint: ctx.accounts.reward_mint.to_account_info(),
+                        to: ctx.accounts.owner_reward_account.to_account_info(),
+                        authority: ctx.accounts.pool.to_account_info(),
+                    },
+                    &[signer_seeds],
+                ),
+                paid,
+                ctx.accounts.reward_mint.decimals,
+            )?;
+        }
+
+        emit!(HeroRewardsClaimed {
+            pool: ctx.accounts.pool.key(),
+            owner: ctx.accounts.owner.key(),
+            nft_mint: ctx.accounts.stake.nft_mint,
+            amount: paid,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the hero and pays whatever the reward vault can cover; rewards
+    /// the vault cannot cover at this point are forfeited with the stake.
+    pub fn unstake_hero(ctx: Context<UnstakeHero>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.update_rewards(Clock::get()?.unix_timestamp)?;
+
+        let stake = &ctx.accounts.stake;
+        let pending = (pool.accrued_for(stake.weight).saturating_sub(stake.reward_debt) as u64)
+            .saturating_add(stake.unpaid_rewards);
+        let paid = pending.min(ctx.accounts.reward_vault.amount);
+        pool.total_weight = pool.total_weight.saturating_sub(stake.weight);
+
+        let collection_mint = pool.collection_mint;
+        let signer_seeds: &[&[u8]] = &[
+            b"hero_staking_pool",
+            collection_mint.as_ref(),
+            &[pool.bump],
+        ];
+
+        if paid > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.reward_token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.reward_vault.to_account_info(),
+                        mint: ctx.accounts.reward_mint.to_account_info(),
+                        to: ctx.accounts.owner_reward_account.to_account_info(),
+                        authority: ctx.accounts.pool.to_account_info(),
+                    },
+                    &[signer_seeds],
+                ),
+                paid,
+                ctx.accounts.reward_mint.decimals,
+            )?;
+        }
+
+        release_from_vault(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.mint.to_account_info(),
+            &ctx.accounts.stake_vault.to_account_info(),
+            &ctx.accounts.owner_nft_ata.to_account_info(),
+            &ctx.accounts.pool.to_account_info(),
+            &ctx.accounts.owner.to_account_info(),
+            &[signer_seeds],
+            1,
+        )?;
+
+        emit!(HeroUnstaked {
+            pool: ctx.accounts.pool.key(),
+            owner: ctx.accounts.owner.key(),
+            nft_mint: ctx.accounts.mint.key(),
+            rewards_paid: paid,
+            rewards_forfeited: pending - paid,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_uog_staking_pool(ctx: Context<CreateUogStakingPool>, reward_rate_per_second: u64, boost_bps: [u16; 3], early_withdraw_penalty_bps: u16) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+        require!(
+            boost_bps.iter().all(|b| *b >= 10_000),
+            MarketplaceError::InvalidStakingConfig
+        );
+        require!(early_withdraw_penalty_bps <= 10_000, MarketplaceError::InvalidFeeBps);
+
+        let pool = &mut ctx.accounts.pool;
+        pool.uog_mint = ctx.accounts.uog_mint.key();
+        pool.stake_vault = ctx.accounts.stake_vault.key();
+        pool.reward_vault = ctx.accounts.reward_vault.key();
+        pool.reward_rate_per_second = reward_rate_per_second;
+        pool.boost_bps = boost_bps;
+        pool.early_withdraw_penalty_bps = early_withdraw_penalty_bps;
+        pool.total_staked = 0;
+        pool.total_weight = 0;
+        pool.acc_reward_per_weight = 0;
+        pool.last_update_ts = Clock::get()?.unix_timestamp;
+        pool.bump = *ctx.bumps.get("pool").unwrap();
+        pool.stake_vault_bump = *ctx.bumps.get("stake_vault").unwrap();
+        pool.reward_vault_bump = *ctx.bumps.get("reward_vault").unwrap();
+
+        emit!(UogStakingPoolCreated {
+            uog_mint: pool.uog_mint,
+            reward_rate_per_second,
+            boost_bps,
+            early_withdraw_penalty_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Stakes UOG into one of the lockup tiers (0 = 30d, 1 = 90d, 2 = 180d).
+    /// Topping up an existing position restarts its lockup.
+    pub fn stake_uog(ctx: Context<StakeUog>, tier: u8, amount: u64) -> Result<()> {
+        require!((tier as usize) < UOG_LOCKUP_DAYS.len(), MarketplaceError::InvalidLockupTier);
+        require!(amount > 0, MarketplaceError::InvalidAmount);
+
+        let received = amount
+            .checked_sub(transfer_fee_for(&ctx.accounts.uog_mint, amount)?)
+            .ok_or(MarketplaceError::MathOverflow)?;
+
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.owner_token_account.to_account_info(),
+                    mint: ctx.accounts.uog_mint.to_account_info(),
+                    to: ctx.accounts.stake_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.uog_mint.decimals,
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &mut ctx.accounts.pool;
+        pool.update_rewards(now)?;
+
+        let stake = &mut ctx.accounts.stake;
+        if stake.owner == Pubkey::default() {
+            stake.owner = ctx.accounts.owner.key();
+            stake.uog_mint = pool.uog_mint;
+            stake.tier = tier;
+            stake.bump = *ctx.bumps.get("stake").unwrap();
+        } else {
+            let accrued = pool.accrued_for(stake.weight);
+            stake.unpaid_rewards = stake
+                .unpaid_rewards
+                .saturating_add(accrued.saturating_sub(stake.reward_debt) as u64);
+        }
+
+        let old_weight = stake.weight;
+        stake.amount = stake.amount.checked_add(received).ok_or(MarketplaceError::MathOverflow)?;
+        stake.weight = ((stake.amount as u128) * pool.boost_bps[tier as usize] as u128 / 10_000) as u64;
+        stake.reward_debt = pool.accrued_for(stake.weight);
+        stake.unlock_ts = now + UOG_LOCKUP_DAYS[tier as usize] * SECONDS_PER_DAY;
+
+        pool.total_staked = pool.total_staked.checked_add(received).ok_or(MarketplaceError::MathOverflow)?;
+        pool.total_weight = pool
+            .total_weight
+            .saturating_sub(old_weight)
+            .checked_add(stake.weight)
+            .ok_or(MarketplaceError::MathOverflow)?;
+
+        emit!(UogStaked {
+            owner: stake.owner,
+            tier,
+            amount: received,
+            total_amount: stake.amount,
+            unlock_ts: stake.unlock_ts,
+        });
+
+        Ok(())
+    }
+
+    pub fn claim_uog_rewards(ctx: Context<ClaimUogRewards>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.update_rewards(Clock::get()?.unix_timestamp)?;
+
+        let stake = &mut ctx.accounts.stake;
+        let accrued = pool.accrued_for(stake.weight);
+        let pending = (accrued.saturating_sub(stake.reward_debt) as u64)
+            .saturating_add(stake.unpaid_rewards);
+        let paid = pending.min(ctx.accounts.reward_vault.amount);
+        stake.reward_debt = accrued;
+        stake.unpaid_rewards = pending - paid;
+
+        let signer_seeds: &[&[u8]] = &[b"uog_staking_pool", &[pool.bump]];
+
+        if paid > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.reward_vault.to_account_info(),
+                        mint: ctx.accounts.uog_mint.to_account_info(),
+                        to: ctx.accounts.owner_token_account.to_account_info(),
+                        authority: ctx.accounts.pool.to_account_info(),
+                    },
+                    &[signer_seeds],
+                ),
+                paid,
+                ctx.accounts.uog_mint.decimals,
+            )?;
+        }
+
+        emit!(UogRewardsClaimed {
+            owner: ctx.accounts.owner.key(),
+            tier: ctx.accounts.stake.tier,
+            amount: paid,
+        });
+
+        Ok(())
+    }
+
+    /// Returns principal plus rewards once the lockup has elapsed; rewards the
+    /// vault cannot cover at this point are forfeited with the position.
+    pub fn unstake_uog(ctx: Context<UnstakeUog>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= ctx.accounts.stake.unlock_ts, MarketplaceError::StakeLocked);
+
+        let pool = &mut ctx.accounts.pool;
+        pool.update_rewards(now)?;
+
+        let stake = &ctx.accounts.stake;
+        let pending = (pool.accrued_for(stake.weight).saturating_sub(stake.reward_debt) as u64)
+            .saturating_add(stake.unpaid_rewards);
+        let paid = pending.min(ctx.accounts.reward_vault.amount);
+        let principal = stake.amount;
+        pool.total_staked = pool.total_staked.saturating_sub(principal);
+        pool.total_weight = pool.total_weight.saturating_sub(stake.weight);
+
+        let signer_seeds: &[&[u8]] = &[b"uog_staking_pool", &[pool.bump]];
+
+        for (vault, amount) in [
+            (ctx.accounts.stake_vault.to_account_info(), principal),
+            (ctx.accounts.reward_vault.to_account_info(), paid),
+        ] {
+            if amount == 0 {
+                continue;
+            }
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: vault,
+                        mint: ctx.accounts.uog_mint.to_account_info(),
+                        to: ctx.accounts.owner_token_account.to_account_info(),
+                        authority: ctx.accounts.pool.to_account_info(),
+                    },
+                    &[signer_seeds],
+                ),
+                amount,
+                ctx.accounts.uog_mint.decimals,
+            )?;
+        }
+
+        emit!(UogUnstaked {
+            owner: ctx.accounts.owner.key(),
+            tier: ctx.accounts.stake.tier,
+            amount: principal,
+            rewards_paid: paid,
+            penalty: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Exits a position before its lockup ends. Pending rewards are forfeited
+    /// and `early_withdraw_penalty_bps` of the principal goes to the treasury.
+    pub fn emergency_withdraw_uog(ctx: Context<EmergencyWithdrawUog>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.update_rewards(Clock::get()?.unix_timestamp)?;
+
+        let stake = &ctx.accounts.stake;
+        let principal = stake.amount;
+        let penalty = if Clock::get()?.unix_timestamp >= stake.unlock_ts {
+            0
+        } else {
+            ((principal as u128) * pool.early_withdraw_penalty_bps as u128 / 10_000) as u64
+        };
+        pool.total_staked = pool.total_staked.saturating_sub(principal);
+        pool.total_weight = pool.total_weight.saturating_sub(stake.weight);
+
+        let signer_seeds: &[&[u8]] = &[b"uog_staking_pool", &[pool.bump]];
+
+        for (destination, amount) in [
+            (ctx.accounts.owner_token_account.to_account_info(), principal - penalty),
+            (ctx.accounts.treasury_token_account.to_account_info(), penalty),
+        ] {
+            if amount == 0 {
+                continue;
+            }
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.stake_vault.to_account_info(),
+                        mint: ctx.accounts.uog_mint.to_account_info(),
+                        to: destination,
+                        authority: ctx.accounts.pool.to_account_info(),
+                    },
+                    &[signer_seeds],
+                ),
+                amount,
+                ctx.accounts.uog_mint.decimals,
+            )?;
+        }
+
+        emit!(UogUnstaked {
+            owner: ctx.accounts.owner.key(),
+            tier: ctx.accounts.stake.tier,
+            amount: principal - penalty,
+            rewards_paid: 0,
+            penalty,
+        });
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_gatcha_pack(ctx: Context<CreateGatchaPack>, pack_id: u64, price: u64, use_sol: bool, rarity_weights: [u16; 4], supply: u64, hero_name: String, symbol: String, base_uri: String, pity: PityRules) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+        require!(
+            rarity_weights.iter().any(|w| *w > 0),
+            MarketplaceError::InvalidGatchaPack
+        );
+        require!(
+            hero_name.len() <= MAX_PACK_NAME_LEN
+                && symbol.len() <= MAX_PACK_SYMBOL_LEN
+                && base_uri.len() <= MAX_PACK_URI_LEN,
+            MarketplaceError::InvalidGatchaPack
+        );
+        require!(
+            pity.legendary_rate_up_after == 0 || pity.legendary_rate_up_bps >= 10_000,
+            MarketplaceError::InvalidGatchaPack
+        );
+
+        let pack = &mut ctx.accounts.pack;
+        pack.pack_id = pack_id;
+        pack.price = price;
+        pack.use_sol = use_sol;
+        pack.payment_mint = ctx.accounts.payment_mint.key();
+        pack.rarity_weights = rarity_weights;
+        pack.supply = supply;
+        pack.sold = 0;
+        pack.opened = 0;
+        pack.hero_name = hero_name;
+        pack.symbol = symbol;
+        pack.base_uri = base_uri;
+        pack.pity = pity;
+        pack.bump = *ctx.bumps.get("pack").unwrap();
+
+        emit!(GatchaPackCreated {
+            pack: pack.key(),
+            pack_id,
+            price,
+            payment_mint: pack.payment_mint,
+            rarity_weights,
+            supply,
+            pity,
+        });
+
+        Ok(())
+    }
+
+    /// Buys a pack, committing to `commitment = hash(secret)` for the reveal.
+    pub fn buy_pack(ctx: Context<BuyPack>, commitment: [u8; 32]) -> Result<()> {
+        let pack = &mut ctx.accounts.pack;
+        require!(pack.sold < pack.supply, MarketplaceError::PackSoldOut);
+
+        let buyer_payment_info = ctx.accounts.buyer_payment_ata.as_ref().map(|a| a.to_account_info());
+        let treasury_token_info = ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info());
+        let referrer_destination_info = ctx.accounts.referrer_destination.as_ref().map(|a| a.to_account_info());
+
+        // Only the trade-fee portion of the price is shared with referrers
+        let fee = pack.price.saturating_mul(ctx.accounts.market_config.trade_fee_bps as u64) / 10_000;
+        let fee_to_treasury = pay_referral_share(
+            fee,
+            ctx.accounts.market_config.referral_share_bps,
+            &ctx.accounts.buyer.to_account_info(),
+            buyer_payment_info.as_ref(),
+            if pack.use_sol { None } else { Some(&ctx.accounts.payment_mint) },
+            Some(&ctx.accounts.payment_token_program.to_account_info()),
+            Some(&ctx.accounts.system_program.to_account_info()),
+            ctx.accounts.referral.as_ref(),
+            ctx.accounts.referrer_stats.as_mut(),
+            referrer_destination_info.as_ref(),
+        )?;
+        let referral_paid = fee - fee_to_treasury;
+
+        pay_fee(
+            pack.use_sol,
+            &ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.treasury_pda.to_account_info(),
+            buyer_payment_info.as_ref(),
+            treasury_token_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.payment_token_program.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            pack.price - referral_paid,
+        )?;
+
+        let purchase = &mut ctx.accounts.purchase;
+        purchase.pack = pack.key();
+        purchase.buyer = ctx.accounts.buyer.key();
+        purchase.index = pack.sold;
+        purchase.commitment = commitment;
+        purchase.price_paid = pack.price;
+        purchase.purchased_slot = Clock::get()?.slot;
+        purchase.bump = *ctx.bumps.get("purchase").unwrap();
+
+        pack.sold += 1;
+
+        emit!(PackPurchased {
+            pack: purchase.pack,
+            buyer: purchase.buyer,
+            index: purchase.index,
+            price: pack.price,
+        });
+
+        Ok(())
+    }
+
+    /// Reveals the buyer's secret and rolls the rarity from it combined with
+    /// the first slot hash after purchase, mints the hero to the buyer and
+    /// records the rarity in its `HeroRarity` entry.
+    ///
+    /// Once `PACK_REVEAL_WINDOW_SLOTS` have passed anyone can open the
+    /// purchase: `secret` is ignored and the hero is Common without advancing
+    /// the buyer's pity, so sitting on an unfavourable roll gains nothing.
+    pub fn open_pack(ctx: Context<OpenPack>, secret: [u8; 32]) -> Result<()> {
+        let purchase = &ctx.accounts.purchase;
+        let expired = Clock::get()?.slot > purchase.purchased_slot + PACK_REVEAL_WINDOW_SLOTS;
+
+        let counter = &mut ctx.accounts.pity_counter;
+        if counter.player == Pubkey::default() {
+            counter.pack = ctx.accounts.pack.key();
+            counter.player = ctx.accounts.buyer.key();
+            counter.bump = *ctx.bumps.get("pity_counter").unwrap();
+        }
+
+        let PityPull { rarity, pity_hit, rate_up } = if expired {
+            PityPull { rarity: Rarity::Common, pity_hit: false, rate_up: false }
+        } else {
+            require!(
+                hashv(&[&secret]).to_bytes() == purchase.commitment,
+                MarketplaceError::InvalidReveal
+            );
+            require_keys_eq!(ctx.accounts.payer.key(), purchase.buyer, MarketplaceError::Unauthorized);
+
+            let slot_hash = slot_hash_after(&ctx.accounts.slot_hashes, purchase.purchased_slot)?;
+            let seed = hashv(&[
+                &secret,
+                &slot_hash,
+                purchase.pack.as_ref(),
+                &purchase.index.to_le_bytes(),
+            ]);
+            let roll = u64::from_le_bytes(seed.to_bytes()[..8].try_into().unwrap());
+
+            let rules = ctx.accounts.pack.pity;
+            let weights = ctx.accounts.pack.rarity_weights;
+            roll_with_pity(&rules, &weights, counter, roll)
+        };
+
+        let pack = &mut ctx.accounts.pack;
+        pack.opened += 1;
+        let name = format!("{} #{}", pack.hero_name, pack.opened);
+        let symbol = pack.symbol.clone();
+        let uri = format!("{}{}.json", pack.base_uri, rarity.as_str().to_ascii_lowercase());
+
+        let mint_key = ctx.accounts.mint.key();
+        let mint_auth_seeds: &[&[u8]] = &[
+            b"mint_auth",
+            mint_key.as_ref(),
+            &[*ctx.bumps.get("mint_authority").unwrap()],
+        ];
+
+        token_interface::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.buyer_nft_ata.to_account_info(),
+                    authority: ctx.accounts.mint_authority.to_account_info(),
+                },
+                &[mint_auth_seeds],
+            ),
+            1,
+        )?;
+
+        if has_embedded_metadata(&ctx.accounts.mint)? {
+            fund_embedded_metadata_rent(
+                &ctx.accounts.payer.to_account_info(),
+                &ctx.accounts.mint.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                &name,
+                &symbol,
+                &uri,
+            )?;
+
+            invoke_signed(
+                &spl_token_metadata_interface::instruction::initialize(
+                    &token_2022::ID,
+                    &mint_key,
+                    &ctx.accounts.update_authority.key(),
+                    &mint_key,
+                    &ctx.accounts.mint_authority.key(),
+                    name.clone(),
+                    symbol.clone(),
+                    uri.clone(),
+                ),
+                &[
+                    ctx.accounts.mint.to_account_info(),
+                    ctx.accounts.update_authority.to_account_info(),
+                    ctx.accounts.mint_authority.to_account_info(),
+                ],
+                &[mint_auth_seeds],
+            )?;
+        } else {
+            let (expected_metadata, _) = MplMetadata::find_pda(&mint_key);
+            require_keys_eq!(
+                ctx.accounts.metadata.key(),
+                expected_metadata,
+                MarketplaceError::InvalidNFT
+            );
+
+            CreateMetadataAccountV3CpiBuilder::new(&ctx.accounts.token_metadata_program.to_account_info())
+                .metadata(&ctx.accounts.metadata.to_account_info())
+                .mint(&ctx.accounts.mint.to_account_info())
+                .mint_authority(&ctx.accounts.mint_authority.to_account_info())
+                .payer(&ctx.accounts.payer.to_account_info())
+                .update_authority(&ctx.accounts.update_authority.to_account_info(), false)
+                .system_program(&ctx.accounts.system_program.to_account_info())
+                .data(DataV2 {
+                    name: name.clone(),
+                    symbol,
+                    uri,
+                    seller_fee_basis_points: 0,
+                    creators: None,
+                    collection: Some(mpl_token_metadata::types::Collection {
+                        verified: false,
+                        key: ctx.accounts.hero_collection.hero_mint_config.collection_mint,
+                    }),
+                    uses: None,
+                })
+                .is_mutable(true)
+                .invoke_signed(&[mint_auth_seeds])?;
+        }
+
+        ctx.accounts.hero_collection.add_hero(
+            &ctx.accounts.mint,
+            &ctx.accounts.mint_authority.to_account_info(),
+            mint_auth_seeds,
+            &ctx.accounts.metadata.to_account_info(),
+            &ctx.accounts.token_metadata_program.to_account_info(),
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        token_interface::set_authority(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                SetAuthority {
+                    current_authority: ctx.accounts.mint_authority.to_account_info(),
+                    account_or_mint: ctx.accounts.mint.to_account_info(),
+                },
+                &[mint_auth_seeds],
+            ),
+            AuthorityType::MintTokens,
+            None,
+        )?;
+
+        let hero_rarity = &mut ctx.accounts.hero_rarity;
+        hero_rarity.nft_mint = mint_key;
+        hero_rarity.rarity = rarity;
+        hero_rarity.bump = *ctx.bumps.get("hero_rarity").unwrap();
+
+        emit!(PackOpened {
+            pack: ctx.accounts.pack.key(),
+            buyer: ctx.accounts.buyer.key(),
+            nft_mint: mint_key,
+            rarity,
+            name,
+            pity_hit,
+            rate_up,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_tournament(ctx: Context<CreateTournament>, tournament_id: u64, entry_fee: u64, use_sol: bool, min_participants: u16, max_participants: u16, registration_end_ts: i64, payout_bps: Vec<u16>, house_cut_bps: u16, result_oracle: Pubkey) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+        require!(
+            min_participants <= max_participants && max_participants > 0,
+            MarketplaceError::InvalidTournament
+        );
+        require!(
+            registration_end_ts > Clock::get()?.unix_timestamp,
+            MarketplaceError::InvalidTournament
+        );
+        require!(
+            !payout_bps.is_empty()
+                && payout_bps.len() <= MAX_PAYOUT_PLACES
+                && payout_bps.len() <= min_participants.max(1) as usize
+                && payout_bps.iter().map(|b| *b as u32).sum::<u32>() == 10_000,
+            MarketplaceError::InvalidTournament
+        );
+        require!(house_cut_bps <= 10_000, MarketplaceError::InvalidFeeBps);
+
+        // Ids only move forward, so a settled or swept tournament's address
+        // (and the oracle signatures and entry receipts bound to it) is never
+        // reissued
+        let counter = &mut ctx.accounts.tournament_counter;
+        require!(
+            tournament_id >= counter.next_tournament_id,
+            MarketplaceError::InvalidTournament
+        );
+        counter.next_tournament_id = tournament_id
+            .checked_add(1)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        counter.bump = *ctx.bumps.get("tournament_counter").unwrap();
+
+        let vault = if use_sol {
+            Pubkey::default()
+        } else {
+            let vault = ctx
+                .accounts
+                .vault
+                .as_ref()
+                .ok_or(MarketplaceError::MissingEscrowVault)?;
+            require!(vault.amount == 0, MarketplaceError::InvalidTournament);
+            vault.key()
+        };
+
+        let tournament = &mut ctx.accounts.tournament;
+        tournament.tournament_id = tournament_id;
+        tournament.payment_mint = ctx.accounts.payment_mint.key();
+        tournament.use_sol = use_sol;
+        tournament.vault = vault;
+        tournament.entry_fee = entry_fee;
+        tournament.min_participants = min_participants;
+        tournament.max_participants = max_participants;
+        tournament.participant_count = 0;
+        tournament.entry_pool = 0;
+        tournament.sponsor_pool = 0;
+        tournament.registration_end_ts = registration_end_ts;
+        tournament.payout_bps = payout_bps;
+        tournament.house_cut_bps = house_cut_bps;
+        tournament.result_oracle = result_oracle;
+        tournament.status = TournamentStatus::Open;
+        tournament.bump = *ctx.bumps.get("tournament").unwrap();
+        tournament.cancelled_at = 0;
+
+        emit!(TournamentCreated {
+            tournament: tournament.key(),
+            tournament_id,
+            payment_mint: tournament.payment_mint,
+            entry_fee,
+            registration_end_ts,
+        });
+
+        Ok(())
+    }
+
+    /// Adds studio (or any sponsor) funds to the prize pool.
+    pub fn sponsor_tournament(ctx: Context<SponsorTournament>, amount: u64) -> Result<()> {
+        require!(amount > 0, MarketplaceError::InvalidAmount);
+        require!(
+            ctx.accounts.tournament.status == TournamentStatus::Open,
+            MarketplaceError::TournamentClosed
+        );
+
+        let sponsor_payment_info = ctx.accounts.sponsor_payment_ata.as_ref().map(|a| a.to_account_info());
+        let vault_info = ctx.accounts.vault.as_ref().map(|a| a.to_account_info());
+        let received = if ctx.accounts.tournament.use_sol {
+            amount
+        } else {
+            amount.saturating_sub(transfer_fee_for(&ctx.accounts.payment_mint, amount)?)
+        };
+
+        pay_fee(
+            ctx.accounts.tournament.use_sol,
+            &ctx.accounts.sponsor.to_account_info(),
+            &ctx.accounts.tournament.to_account_info(),
+            sponsor_payment_info.as_ref(),
+            vault_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.payment_token_program.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            amount,
+        )?;
+
+        let tournament = &mut ctx.accounts.tournament;
+        tournament.sponsor_pool = tournament
+            .sponsor_pool
+            .checked_add(received)
+            .ok_or(MarketplaceError::MathOverflow)?;
+
+        let sponsorship = &mut ctx.accounts.sponsorship;
+        sponsorship.tournament = tournament.key();
+        sponsorship.sponsor = ctx.accounts.sponsor.key();
+        sponsorship.amount = sponsorship
+            .amount
+            .checked_add(received)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        sponsorship.bump = *ctx.bumps.get("sponsorship").unwrap();
+
+        emit!(TournamentSponsored {
+            tournament: tournament.key(),
+            sponsor: ctx.accounts.sponsor.key(),
+            amount: received,
+        });
+
+        Ok(())
+    }
+
+    pub fn join_tournament(ctx: Context<JoinTournament>) -> Result<()> {
+        let tournament = &ctx.accounts.tournament;
+        require!(
+            tournament.status == TournamentStatus::Open
+                && Clock::get()?.unix_timestamp < tournament.registration_end_ts,
+            MarketplaceError::TournamentClosed
+        );
+        require!(
+            tournament.participant_count < tournament.max_participants,
+            MarketplaceError::TournamentFull
+        );
+
+        let player_payment_info = ctx.accounts.player_payment_ata.as_ref().map(|a| a.to_account_info());
+        let vault_info = ctx.accounts.vault.as_ref().map(|a| a.to_account_info());
+        let entry_fee = tournament.entry_fee;
+        let received = if tournament.use_sol {
+            entry_fee
+        } else {
+            entry_fee.saturating_sub(transfer_fee_for(&ctx.accounts.payment_mint, entry_fee)?)
+        };
+
+        pay_fee(
+            tournament.use_sol,
+            &ctx.accounts.player.to_account_info(),
+            &ctx.accounts.tournament.to_account_info(),
+            player_payment_info.as_ref(),
+            vault_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.payment_token_program.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            entry_fee,
+        )?;
+
+        let tournament = &mut ctx.accounts.tournament;
+        tournament.participant_count += 1;
+        tournament.entry_pool = tournament
+            .entry_pool
+            .checked_add(received)
+            .ok_or(MarketplaceError::MathOverflow)?;
+
+        let participant = &mut ctx.accounts.participant;
+        participant.tournament = tournament.key();
+        participant.player = ctx.accounts.player.key();
+        participant.entry_paid = received;
+        participant.bump = *ctx.bumps.get("participant").unwrap();
+
+        emit!(TournamentJoined {
+            tournament: participant.tournament,
+            player: participant.player,
+            entry_paid: received,
+            participant_count: tournament.participant_count,
+        });
+
+        Ok(())
+    }
+
+    /// Pays out the prize pool to `standings` (first place first), which must
+    /// be signed by the tournament's result oracle in an Ed25519 program
+    /// instruction placed right before this one. The signed message is the
+    /// tournament address and its little-endian id followed by the standings.
+    /// Remaining accounts come in pairs per place, in the same order: the
+    /// winner's `TournamentParticipant` record, then the payout destination
+    /// (winner wallet for SOL, winner token account for SPL pools).
+    pub fn settle_tournament<'info>(ctx: Context<'_, '_, '_, 'info, SettleTournament<'info>>, standings: Vec<Pubkey>) -> Result<()> {
+        let tournament = &ctx.accounts.tournament;
+        require!(
+            tournament.status == TournamentStatus::Open
+                && Clock::get()?.unix_timestamp >= tournament.registration_end_ts,
+            MarketplaceError::TournamentClosed
+        );
+        require!(
+            tournament.participant_count >= tournament.min_participants,
+            MarketplaceError::InvalidTournament
+        );
+        require!(
+            standings.len() == tournament.payout_bps.len()
+                && ctx.remaining_accounts.len() == standings.len() * 2,
+            MarketplaceError::InvalidTournament
+        );
+
+        let mut message = tournament.key().to_bytes().to_vec();
+        message.extend_from_slice(&tournament.tournament_id.to_le_bytes());
+        for winner in standings.iter() {
+            message.extend_from_slice(winner.as_ref());
+        }
+        verify_ed25519_signature(
+            &ctx.accounts.instructions_sysvar,
+            &tournament.result_oracle,
+            &message,
+        )?;
+
+        let house_cut = tournament
+            .entry_pool
+            .saturating_mul(tournament.house_cut_bps as u64)
+            / 10_000;
+        let prize_pool = tournament
+            .entry_pool
+            .saturating_add(tournament.sponsor_pool)
+            .saturating_sub(house_cut);
+
+        let tournament_id = tournament.tournament_id.to_le_bytes();
+        let signer_seeds: &[&[u8]] = &[b"tournament", tournament_id.as_ref(), &[tournament.bump]];
+        let tournament_info = ctx.accounts.tournament.to_account_info();
+        let vault_info = ctx.accounts.vault.as_ref().map(|a| a.to_account_info());
+        let vault_balance = ctx.accounts.vault.as_ref().map(|a| a.amount).unwrap_or(0);
+        let token_program_info = ctx.accounts.payment_token_program.to_account_info();
+
+        let mut paid_out = 0u64;
+        for (place, ((winner, accounts), bps)) in standings
+            .iter()
+            .zip(ctx.remaining_accounts.chunks(2))
+            .zip(tournament.payout_bps.iter())
+            .enumerate()
+        {
+            require!(!standings[..place].contains(winner), MarketplaceError::InvalidTournament);
+            let participant = Account::<TournamentParticipant>::try_from(&accounts[0])?;
+            require!(
+                participant.tournament == tournament.key() && participant.player == *winner,
+                MarketplaceError::InvalidTournament
+            );
+
+            let destination = &accounts[1];
+            if tournament.use_sol {
+                require_keys_eq!(destination.key(), *winner, MarketplaceError::InvalidOwner);
+            } else {
+                let account = InterfaceAccount::<TokenAccount>::try_from(destination)?;
+                require_keys_eq!(account.owner, *winner, MarketplaceError::InvalidOwner);
+                require_keys_eq!(account.mint, tournament.payment_mint, MarketplaceError::InvalidMint);
+            }
+
+            let prize = prize_pool.saturating_mul(*bps as u64) / 10_000;
+            pay_from_escrow(
+                tournament.use_sol,
+                &tournament_info,
+                destination,
+                vault_info.as_ref(),
+                &ctx.accounts.payment_mint,
+                &token_program_info,
+                &[signer_seeds],
+                prize,
+            )?;
+            paid_out += prize;
+        }
+
+        // House cut plus rounding dust.
+        let to_treasury = tournament
+            .entry_pool
+            .saturating_add(tournament.sponsor_pool)
+            .saturating_sub(paid_out);
+        let treasury_token_info = ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info());
+        if tournament.use_sol {
+            pay_from_escrow(
+                true,
+                &tournament_info,
+                &ctx.accounts.treasury_pda.to_account_info(),
+                None,
+                &ctx.accounts.payment_mint,
+                &token_program_info,
+                &[signer_seeds],
+                to_treasury,
+            )?;
+        } else {
+            let vault = vault_info.as_ref().ok_or(MarketplaceError::MissingEscrowVault)?;
+            let treasury_token = treasury_token_info.as_ref().ok_or(MarketplaceError::InvalidMint)?;
+            release_from_vault(
+                &token_program_info,
+                &ctx.accounts.payment_mint.to_account_info(),
+                vault,
+                treasury_token,
+                &tournament_info,
+                &ctx.accounts.treasury_pda.to_account_info(),
+                &[signer_seeds],
+                vault_balance.saturating_sub(paid_out),
+            )?;
+        }
+
+        ctx.accounts.tournament.status = TournamentStatus::Settled;
+
+        emit!(TournamentSettled {
+            tournament: ctx.accounts.tournament.key(),
+            standings,
+            prize_pool,
+            house_cut: to_treasury,
+        });
+
+        Ok(())
+    }
+
+    /// Reclaims the rent of a participant record once its tournament account
+    /// has been closed by settlement.
+    pub fn close_tournament_entry(ctx: Context<CloseTournamentEntry>) -> Result<()> {
+        require!(
+            ctx.accounts.tournament.data_is_empty(),
+            MarketplaceError::TournamentClosed
+        );
+
+        emit!(TournamentEntryClosed {
+            tournament: ctx.accounts.tournament.key(),
+            player: ctx.accounts.player.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_tournament(ctx: Context<CancelTournament>) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+
+        let tournament = &mut ctx.accounts.tournament;
+        require!(
+            tournament.status == TournamentStatus::Open,
+            MarketplaceError::TournamentClosed
+        );
+        tournament.status = TournamentStatus::Cancelled;
+        tournament.cancelled_at = Clock::get()?.unix_timestamp;
+
+        emit!(TournamentCancelled {
+            tournament: tournament.key(),
+            participant_count: tournament.participant_count,
+        });
+
+        Ok(())
+    }
+
+    /// Returns a participant's entry fee from a cancelled tournament, or one
+    /// whose registration closed below `min_participants` (which cancels it).
+    /// The participant receipt is closed, so each entry refunds once.
+    pub fn claim_refund(ctx: Context<ClaimRefund>) -> Result<()> {
+        let tournament = &mut ctx.accounts.tournament;
+        if tournament.cancel_if_under_filled(Clock::get()?.unix_timestamp) {
+            emit!(TournamentCancelled {
+                tournament: tournament.key(),
+                participant_count: tournament.participant_count,
+            });
+        }
+        require!(
+            tournament.status == TournamentStatus::Cancelled,
+            MarketplaceError::TournamentClosed
+        );
+
+        let refund = ctx.accounts.participant.entry_paid;
+        tournament.participant_count -= 1;
+        tournament.entry_pool = tournament.entry_pool.saturating_sub(refund);
+
+        let tournament_id = tournament.tournament_id.to_le_bytes();
+        let signer_seeds: &[&[u8]] = &[b"tournament", tournament_id.as_ref(), &[tournament.bump]];
+        let use_sol = tournament.use_sol;
+        let destination = match &ctx.accounts.player_payment_ata {
+            Some(ata) if !use_sol => ata.to_account_info(),
+            _ => ctx.accounts.player.to_account_info(),
+        };
+        let vault_info = ctx.accounts.vault.as_ref().map(|a| a.to_account_info());
+
+        pay_from_escrow(
+            use_sol,
+            &ctx.accounts.tournament.to_account_info(),
+            &destination,
+            vault_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.payment_token_program.to_account_info(),
+            &[signer_seeds],
+            refund,
+        )?;
+
+        emit!(TournamentRefundClaimed {
+            tournament: ctx.accounts.tournament.key(),
+            player: ctx.accounts.player.key(),
+            amount: refund,
+        });
+
+        Ok(())
+    }
+
+    /// Returns a sponsor's contribution from a cancelled tournament, or one
+    /// whose registration closed below `min_participants` (which cancels it).
+    /// The sponsor receipt is closed, so each contribution refunds once.
+    pub fn claim_sponsor_refund(ctx: Context<ClaimSponsorRefund>) -> Result<()> {
+        let tournament = &mut ctx.accounts.tournament;
+        if tournament.cancel_if_under_filled(Clock::get()?.unix_timestamp) {
+            emit!(TournamentCancelled {
+                tournament: tournament.key(),
+                participant_count: tournament.participant_count,
+            });
+        }
+        require!(
+            tournament.status == TournamentStatus::Cancelled,
+            MarketplaceError::TournamentClosed
+        );
+
+        let refund = ctx.accounts.sponsorship.amount;
+        tournament.sponsor_pool = tournament.sponsor_pool.saturating_sub(refund);
+
+        let tournament_id = tournament.tournament_id.to_le_bytes();
+        let signer_seeds: &[&[u8]] = &[b"tournament", tournament_id.as_ref(), &[tournament.bump]];
+        let use_sol = tournament.use_sol;
+        let destination = match &ctx.accounts.sponsor_payment_ata {
+            Some(ata) if !use_sol => ata.to_account_info(),
+            _ => ctx.accounts.sponsor.to_account_info(),
+        };
+        let vault_info = ctx.accounts.vault.as_ref().map(|a| a.to_account_info());
+
+        pay_from_escrow(
+            use_sol,
+            &ctx.accounts.tournament.to_account_info(),
+            &destination,
+            vault_info.as_ref(),
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.payment_token_program.to_account_info(),
+            &[signer_seeds],
+            refund,
+        )?;
+
+        emit!(TournamentSponsorRefunded {
+            tournament: ctx.accounts.tournament.key(),
+            sponsor: ctx.accounts.sponsor.key(),
+            amount: refund,
+        });
+
+        Ok(())
+    }
+
+    /// Reclaims the rent of a sponsor receipt once its tournament account
+    /// has been closed by settlement or sweeping.
+    pub fn close_sponsor_receipt(ctx: Context<CloseSponsorReceipt>) -> Result<()> {
+        require!(
+            ctx.accounts.tournament.data_is_empty(),
+            MarketplaceError::TournamentClosed
+        );
+
+        emit!(TournamentSponsorReceiptClosed {
+            tournament: ctx.accounts.tournament.key(),
+            sponsor: ctx.accounts.sponsor.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Once every entrant and sponsor of a cancelled tournament has been
+    /// refunded, or `TOURNAMENT_CLAIM_WINDOW_SECS` after cancellation, sweeps
+    /// whatever is left to the treasury and closes the accounts.
+    pub fn close_cancelled_tournament(ctx: Context<CloseCancelledTournament>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let tournament = &mut ctx.accounts.tournament;
+        if tournament.cancel_if_under_filled(now) {
+            emit!(TournamentCancelled {
+                tournament: tournament.key(),
+                participant_count: tournament.participant_count,
+            });
+        }
+        require!(
+            tournament.status == TournamentStatus::Cancelled,
+            MarketplaceError::TournamentClosed
+        );
+        let claims_done = tournament.participant_count == 0 && tournament.sponsor_pool == 0;
+        require!(
+            claims_done || now >= tournament.cancelled_at.saturating_add(TOURNAMENT_CLAIM_WINDOW_SECS),
+            MarketplaceError::ClaimWindowOpen
+        );
+
+        let tournament = &ctx.accounts.tournament;
+        let swept = tournament.entry_pool.saturating_add(tournament.sponsor_pool);
+        if !tournament.use_sol {
+            let tournament_id = tournament.tournament_id.to_le_bytes();
+            let signer_seeds: &[&[u8]] = &[b"tournament", tournament_id.as_ref(), &[tournament.bump]];
+            let vault = ctx.accounts.vault.as_ref().ok_or(MarketplaceError::MissingEscrowVault)?;
+            let treasury_token = ctx
+                .accounts
+                .treasury_token_account
+                .as_ref()
+                .ok_or(MarketplaceError::InvalidMint)?;
+
+            release_from_vault(
+                &ctx.accounts.payment_token_program.to_account_info(),
+                &ctx.accounts.payment_mint.to_account_info(),
+                &vault.to_account_info(),
+                &treasury_token.to_account_info(),
+                &ctx.accounts.tournament.to_account_info(),
+                &ctx.accounts.treasury_pda.to_account_info(),
+                &[signer_seeds],
+                vault.amount,
+            )?;
+        }
+
+        emit!(TournamentSwept {
+            tournament: ctx.accounts.tournament.key(),
+            swept,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_battle_config(ctx: Context<SetBattleConfig>, battle_server: Pubkey, wager_fee_bps: u16, result_timeout_secs: i64, uog_mint: Pubkey) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+        require!(wager_fee_bps <= 10_000, MarketplaceError::InvalidFeeBps);
+        require!(result_timeout_secs > 0, MarketplaceError::InvalidWager);
+
+        let config = &mut ctx.accounts.battle_config;
+        config.battle_server = battle_server;
+        config.wager_fee_bps = wager_fee_bps;
+        config.result_timeout_secs = result_timeout_secs;
+        config.bump = *ctx.bumps.get("battle_config").unwrap();
+        config.uog_mint = uog_mint;
+
+        emit!(BattleConfigUpdated {
+            battle_server,
+            wager_fee_bps,
+            result_timeout_secs,
+            uog_mint,
+        });
+
+        Ok(())
+    }
+
+    /// Remaining accounts commit the creator's team: for each of the
+    /// `battle::TEAM_SIZE` heroes, the creator's token account holding it
+    /// followed by its `HeroRarity` entry. A disputed result is re-simulated
+    /// from those rarities.
+    pub fn create_wager<'info>(ctx: Context<'_, '_, '_, 'info, CreateWager<'info>>, match_id: u64, opponent: Pubkey, stake_amount: u64) -> Result<()> {
+        require!(stake_amount > 0, MarketplaceError::InvalidAmount);
+        require_keys_neq!(opponent, ctx.accounts.player_a.key(), MarketplaceError::InvalidWager);
+        let (team, rarities) = commit_team(&ctx.accounts.player_a.key(), ctx.remaining_accounts)?;
+
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.player_a_token_account.to_account_info(),
+                    mint: ctx.accounts.uog_mint.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.player_a.to_account_info(),
+                },
+            ),
+            stake_amount,
+            ctx.accounts.uog_mint.decimals,
+        )?;
+
+        let wager = &mut ctx.accounts.wager;
+        wager.match_id = match_id;
+        wager.player_a = ctx.accounts.player_a.key();
+        wager.player_b = opponent;
+        wager.uog_mint = ctx.accounts.uog_mint.key();
+        wager.vault = ctx.accounts.vault.key();
+        wager.stake_amount = stake_amount;
+        wager.team_a = team;
+        wager.team_b = [Pubkey::default(); battle::TEAM_SIZE];
+        wager.team_a_rarity = rarities;
+        wager.team_b_rarity = [Rarity::Common; battle::TEAM_SIZE];
+        wager.status = WagerStatus::Pending;
+        wager.accepted_at = 0;
+        wager.bump = *ctx.bumps.get("wager").unwrap();
+        wager.vault_bump = *ctx.bumps.get("vault").unwrap();
+        wager.accepted_slot = 0;
+        wager.winner = Pubkey::default();
+        wager.battle_seed = [0; 32];
+        wager.resolved_at = 0;
+
+        emit!(WagerCreated {
+            wager: wager.key(),
+            match_id,
+            player_a: wager.player_a,
+            player_b: opponent,
+            stake_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Remaining accounts commit the opponent's team, laid out as in
+    /// `create_wager`.
+    pub fn accept_wager<'info>(ctx: Context<'_, '_, '_, 'info, AcceptWager<'info>>) -> Result<()> {
+        require!(
+            ctx.accounts.wager.status == WagerStatus::Pending,
+            MarketplaceError::InvalidWager
+        );
+        let (team, rarities) = commit_team(&ctx.accounts.player_b.key(), ctx.remaining_accounts)?;
+
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.player_b_token_account.to_account_info(),
+                    mint: ctx.accounts.uog_mint.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.player_b.to_account_info(),
+                },
+            ),
+            ctx.accounts.wager.stake_amount,
+            ctx.accounts.uog_mint.decimals,
+        )?;
+
+        let wager = &mut ctx.accounts.wager;
+        wager.team_b = team;
+        wager.team_b_rarity = rarities;
+        let clock = Clock::get()?;
+        wager.status = WagerStatus::Active;
+        wager.accepted_at = clock.unix_timestamp;
+        wager.accepted_slot = clock.slot;
+
+        emit!(WagerAccepted {
+            wager: wager.key(),
+            player_b: wager.player_b,
+        });
+
+        Ok(())
+    }
+
+    /// Lets the creator pull their stake back before the opponent accepts.
+    pub fn cancel_wager(ctx: Context<CancelWager>) -> Result<()> {
+        let wager = &ctx.accounts.wager;
+        require!(wager.status == WagerStatus::Pending, MarketplaceError::InvalidWager);
+
+        let wager_key = wager.key();
+        let signer_seeds: &[&[u8]] = &[b"wager_vault", wager_key.as_ref(), &[wager.vault_bump]];
+
+        release_from_vault(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.uog_mint.to_account_info(),
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.player_a_token_account.to_account_info(),
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.player_a.to_account_info(),
+            &[signer_seeds],
+            ctx.accounts.vault.amount,
+        )?;
+
+        emit!(WagerCancelled { wager: wager_key });
+
+        Ok(())
+    }
+
+    /// Records `winner` as signed by the battle server in an Ed25519 program
+    /// instruction right before this one, over the wager address, the
+    /// winner, `player_b`, the little-endian stake amount and acceptance
+    /// slot, and the battle seed. The pot is paid by `finalize_wager` once
+    /// `WAGER_DISPUTE_WINDOW_SECS` pass without a dispute.
+    pub fn settle_wager(ctx: Context<SettleWager>, winner: Pubkey, battle_seed: [u8; 32]) -> Result<()> {
+        let wager = &ctx.accounts.wager;
+        require!(wager.status == WagerStatus::Active, MarketplaceError::InvalidWager);
+        require!(
+            winner == wager.player_a || winner == wager.player_b,
+            MarketplaceError::InvalidWager
+        );
+
+        let mut message = wager.key().to_bytes().to_vec();
+        message.extend_from_slice(winner.as_ref());
+        message.extend_from_slice(wager.player_b.as_ref());
+        message.extend_from_slice(&wager.stake_amount.to_le_bytes());
+        message.extend_from_slice(&wager.accepted_slot.to_le_bytes());
+        message.extend_from_slice(&battle_seed);
+        verify_ed25519_signature(
+            &ctx.accounts.instructions_sysvar,
+            &ctx.accounts.battle_config.battle_server,
+            &message,
+        )?;
+
+        let wager = &mut ctx.accounts.wager;
+        wager.status = WagerStatus::Resolved;
+        wager.winner = winner;
+        wager.battle_seed = battle_seed;
+        wager.resolved_at = Clock::get()?.unix_timestamp;
+
+        emit!(WagerResultPosted {
+            wager: wager.key(),
+            winner,
+            resolved_at: wager.resolved_at,
+        });
+
+        Ok(())
+    }
+
+    /// Pays the pot to the posted winner once the dispute window has passed.
+    /// The wager fee goes to the treasury.
+    pub fn finalize_wager(ctx: Context<FinalizeWager>) -> Result<()> {
+        let wager = &ctx.accounts.wager;
+        require!(wager.status == WagerStatus::Resolved, MarketplaceError::InvalidWager);
+        require!(
+            Clock::get()?.unix_timestamp >= wager.resolved_at + WAGER_DISPUTE_WINDOW_SECS,
+            MarketplaceError::DisputeWindowOpen
+        );
+
+        let wager_key = wager.key();
+        let signer_seeds: &[&[u8]] = &[b"wager_vault", wager_key.as_ref(), &[wager.vault_bump]];
+        let outcome = if wager.winner == wager.player_a {
+            battle::Outcome::TeamA
+        } else {
+            battle::Outcome::TeamB
+        };
+
+        let (payout_a, payout_b, fee) = pay_out_wager(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.uog_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.player_a_token_account.to_account_info(),
+            &ctx.accounts.player_b_token_account.to_account_info(),
+            &ctx.accounts.treasury_token_account.to_account_info(),
+            &ctx.accounts.player_a.to_account_info(),
+            &[signer_seeds],
+            ctx.accounts.battle_config.wager_fee_bps,
+            outcome,
+        )?;
+
+        emit!(WagerSettled {
+            wager: wager_key,
+            winner: ctx.accounts.wager.winner,
+            payout: payout_a.max(payout_b),
+            fee,
+        });
+
+        Ok(())
+    }
+
+    /// Returns both stakes when no result was posted within the timeout.
+    pub fn withdraw_expired_wager(ctx: Context<WithdrawExpiredWager>) -> Result<()> {
+        let wager = &ctx.accounts.wager;
+        require!(wager.status == WagerStatus::Active, MarketplaceError::InvalidWager);
+        require!(
+            Clock::get()?.unix_timestamp
+                >= wager.accepted_at + ctx.accounts.battle_config.result_timeout_secs,
+            MarketplaceError::WagerNotExpired
+        );
+
+        let pot = ctx.accounts.vault.amount;
+        let share_b = pot / 2;
+        let wager_key = wager.key();
+        let signer_seeds: &[&[u8]] = &[b"wager_vault", wager_key.as_ref(), &[wager.vault_bump]];
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.vault.to_account_info(),
+                    mint: ctx.accounts.uog_mint.to_account_info(),
+                    to: ctx.accounts.player_b_token_account.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                &[signer_seeds],
+            ),
+            share_b,
+            ctx.accounts.uog_mint.decimals,
+        )?;
+
+        release_from_vault(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.uog_mint.to_account_info(),
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.player_a_token_account.to_account_info(),
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.player_a.to_account_info(),
+            &[signer_seeds],
+            pot - share_b,
+        )?;
+
+        emit!(WagerExpired { wager: wager_key });
+
+        Ok(())
+    }
+
+    /// Lets either player overturn a posted result within the dispute window
+    /// by re-running the battle on-chain. Both teams' stats come from the
+    /// rarities committed at create/accept, and the seed mixes in the battle
+    /// seed the server signed, which neither player knew when committing.
+    /// A simulated draw splits the pot fee-free.
+    pub fn dispute_wager(ctx: Context<DisputeWager>) -> Result<()> {
+        let wager = &ctx.accounts.wager;
+        require!(wager.status == WagerStatus::Resolved, MarketplaceError::InvalidWager);
+        require!(
+            ctx.accounts.disputer.key() == wager.player_a || ctx.accounts.disputer.key() == wager.player_b,
+            MarketplaceError::Unauthorized
+        );
+        require!(
+            Clock::get()?.unix_timestamp < wager.resolved_at + WAGER_DISPUTE_WINDOW_SECS,
+            MarketplaceError::DisputeWindowClosed
+        );
+
+        let wager_key = wager.key();
+        let seed = hashv(&[wager_key.as_ref(), &wager.battle_seed]);
+        let outcome = battle::simulate(
+            &battle::team_for(&wager.team_a_rarity),
+            &battle::team_for(&wager.team_b_rarity),
+            u64::from_le_bytes(seed.to_bytes()[..8].try_into().unwrap()),
+        );
+        let winner = match outcome {
+            battle::Outcome::TeamA => wager.player_a,
+            battle::Outcome::TeamB => wager.player_b,
+            battle::Outcome::Draw => Pubkey::default(),
+        };
+
+        let signer_seeds: &[&[u8]] = &[b"wager_vault", wager_key.as_ref(), &[wager.vault_bump]];
+        let (payout_a, payout_b, fee) = pay_out_wager(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.uog_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.player_a_token_account.to_account_info(),
+            &ctx.accounts.player_b_token_account.to_account_info(),
+            &ctx.accounts.treasury_token_account.to_account_info(),
+            &ctx.accounts.player_a.to_account_info(),
+            &[signer_seeds],
+            ctx.accounts.battle_config.wager_fee_bps,
+            outcome,
+        )?;
+
+        emit!(WagerDisputeResolved {
+            wager: wager_key,
+            winner,
+            payout: payout_a.max(payout_b),
+            fee,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_distribution(ctx: Context<CreateDistribution>, epoch: u64, merkle_root: [u8; 32], total_amount: u64, num_claims: u32, expires_at: i64) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+        require!(
+            num_claims > 0
+                && num_claims <= MAX_DISTRIBUTION_CLAIMS
+                && expires_at > Clock::get()?.unix_timestamp,
+            MarketplaceError::InvalidDistribution
+        );
+
+        // Every leaf must be claimable, so the vault has to end up holding
+        // `total_amount` after any transfer fee
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.admin_token_account.to_account_info(),
+                    mint: ctx.accounts.uog_mint.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.admin.to_account_info(),
+                },
+            ),
+            gross_up_transfer_fee(&ctx.accounts.uog_mint, total_amount)?,
+            ctx.accounts.uog_mint.decimals,
+        )?;
+
+        let distribution = &mut ctx.accounts.distribution;
+        distribution.epoch = epoch;
+        distribution.merkle_root = merkle_root;
+        distribution.uog_mint = ctx.accounts.uog_mint.key();
+        distribution.vault = ctx.accounts.vault.key();
+        distribution.total_amount = total_amount;
+        distribution.claimed_amount = 0;
+        distribution.num_claims = num_claims;
+        distribution.expires_at = expires_at;
+        distribution.claimed_bitmap = vec![0; claim_bitmap_len(num_claims)];
+        distribution.bump = *ctx.bumps.get("distribution").unwrap();
+
+        emit!(DistributionCreated {
+            distribution: distribution.key(),
+            epoch,
+            merkle_root,
+            total_amount,
+            num_claims,
+            expires_at,
+        });
+
+        Ok(())
+    }
+
+    /// Claims `amount` for leaf `index`, where the leaf is
+    /// `hash(0x00 || index_le || claimant || amount_le)`.
+    pub fn claim_earnings(ctx: Context<ClaimEarnings>, index: u32, amount: u64, proof: Vec<[u8; 32]>) -> Result<()> {
+        let distribution = &mut ctx.accounts.distribution;
+        require!(
+            Clock::get()?.unix_timestamp < distribution.expires_at,
+            MarketplaceError::DistributionExpired
+        );
+        let (byte, bit) = claim_bit(index, distribution.num_claims).ok_or(MarketplaceError::InvalidProof)?;
+        require!(
+            distribution.claimed_bitmap[byte] & bit == 0,
+            MarketplaceError::AlreadyClaimed
+        );
+
+        let claimant = ctx.accounts.claimant.key();
+        let leaf = earnings_leaf(index, &claimant, amount);
+        require!(
+            verify_merkle_proof(&proof, &distribution.merkle_root, leaf),
+            MarketplaceError::InvalidProof
+        );
+
+        distribution.claimed_bitmap[byte] |= bit;
+        distribution.claimed_amount = distribution
+            .claimed_amount
+            .checked_add(amount)
+            .ok_or(MarketplaceError::MathOverflow)?;
+
+        let epoch = distribution.epoch.to_le_bytes();
+        let signer_seeds: &[&[u8]] = &[b"distribution", epoch.as_ref(), &[distribution.bump]];
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.vault.to_account_info(),
+                    mint: ctx.accounts.uog_mint.to_account_info(),
+                    to: ctx.accounts.claimant_token_account.to_account_info(),
+                    authority: ctx.accounts.distribution.to_account_info(),
+                },
+                &[signer_seeds],
+            ),
+            amount,
+            ctx.accounts.uog_mint.decimals,
+        )?;
+
+        emit!(EarningsClaimed {
+            distribution: ctx.accounts.distribution.key(),
+            claimant,
+            index,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// After expiry, returns unclaimed funds to the treasury and closes the
+    /// distribution and its vault.
+    pub fn sweep_distribution(ctx: Context<SweepDistribution>) -> Result<()> {
+        let distribution = &ctx.accounts.distribution;
+        require!(
+            Clock::get()?.unix_timestamp >= distribution.expires_at,
+            MarketplaceError::DistributionActive
+        );
+
+        let epoch = distribution.epoch.to_le_bytes();
+        let signer_seeds: &[&[u8]] = &[b"distribution", epoch.as_ref(), &[distribution.bump]];
+        let swept = ctx.accounts.vault.amount;
+
+        release_from_vault(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.uog_mint.to_account_info(),
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.treasury_token_account.to_account_info(),
+            &ctx.accounts.distribution.to_account_info(),
+            &ctx.accounts.treasury_pda.to_account_info(),
+            &[signer_seeds],
+            swept,
+        )?;
+
+        emit!(DistributionSwept {
+            distribution: ctx.accounts.distribution.key(),
+            epoch: ctx.accounts.distribution.epoch,
+            swept,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_referral_share(ctx: Context<SetReferralShare>, referral_share_bps: u16) -> Result<()> {
+        require_multisig(
+            &ctx.accounts.market_config,
+            &[
+                ctx.accounts.admin.key(),
+                ctx.accounts.signer1.key(),
+                ctx.accounts.signer2.key(),
+            ],
+        )?;
+        require!(referral_share_bps <= MAX_REFERRAL_SHARE_BPS, MarketplaceError::InvalidFeeBps);
+
+        ctx.accounts.market_config.referral_share_bps = referral_share_bps;
+
+        emit!(ReferralShareUpdated { referral_share_bps });
+
+        Ok(())
+    }
+
+    /// Records `referrer` for the signing player. A player's referrer can only
+    /// be set once.
+    pub fn register_referrer(ctx: Context<RegisterReferrer>, referrer: Pubkey) -> Result<()> {
+        let player = ctx.accounts.player.key();
+        require_keys_neq!(referrer, player, MarketplaceError::InvalidReferral);
+
+        let player_referral = &mut ctx.accounts.player_referral;
+        require!(player_referral.referrer.is_none(), MarketplaceError::ReferrerAlreadySet);
+        if player_referral.player == Pubkey::default() {
+            player_referral.player = player;
+            player_referral.bump = *ctx.bumps.get("player_referral").unwrap();
+        }
+        player_referral.referrer = Some(referrer);
+
+        let referrer_referral = &mut ctx.accounts.referrer_referral;
+        if referrer_referral.player == Pubkey::default() {
+            referrer_referral.player = referrer;
+            referrer_referral.bump = *ctx.bumps.get("referrer_referral").unwrap();
+        }
+        referrer_referral.referral_count += 1;
+
+        emit!(ReferrerRegistered {
+            player,
+            referrer,
+            referral_count: referrer_referral.referral_count,
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct SwapToken<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: DEX aggregator program (e.g. DFLOW)
+    pub dex_program: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (SOL & ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    /// Output mint (SPL Token or Token-2022, So111... for native SOL)
+    pub output_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = output_mint,
+        token::authority = treasury_pda
+    )]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = output_mint,
+        token::authority = user
+    )]
+    pub user_out_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Input token account; omitted when the input is native SOL
+    #[account(token::authority = user)]
+    pub user_in_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Optional fee discount for the user
+    pub fee_tier: Option<Account<'info, FeeTier>>,
+
+    /// Token account proving a holding-based fee tier
+    pub fee_tier_holding: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// UOG stake position proving a staking-based fee tier
+    pub fee_tier_stake: Option<Account<'info, UogStake>>,
+
+    /// Payer's referral registry, when they were referred
+    #[account(seeds = [b"referral", user.key().as_ref()], bump = referral.bump)]
+    pub referral: Option<Account<'info, Referral>>,
+
+    /// Referrer's registry, credited with the referral stats
+    #[account(
+        mut,
+        seeds = [b"referral", referrer_stats.player.as_ref()],
+        bump = referrer_stats.bump
+    )]
+    pub referrer_stats: Option<Account<'info, Referral>>,
+
+    /// CHECK: Referrer wallet (SOL) or token account (SPL), checked in pay_referral_share
+    #[account(mut)]
+    pub referrer_destination: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ListingMode {
+    /// NFT stays in the seller wallet with `escrow_signer` approved as delegate
+    Delegate,
+    /// NFT is held in a vault ATA owned by `escrow_signer` until bought or cancelled
+    Custody,
+}
+
+pub const LISTING_SPACE: usize = 8 + 32 + 32 + 8 + 1 + 1 + 1 + 32;
+
+#[account]
+pub struct Listing {
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub price: u64,
+    pub use_sol: bool,
+    pub bump: u8,
+    pub mode: ListingMode,
+    /// Mint the price is denominated in (So111... for native SOL)
+    pub payment_mint: Pubkey,
+}
+
+pub const MARKET_CONFIG_SPACE: usize = 8 + 32 + 2 + 2 + 2 + 1 + 4 + (32 * 5) + 1 + 2 + 8 + 2;
+
+#[account]
+pub struct MarketConfig {
+    pub admin: Pubkey,
+    pub mint_fee_bps: u16,
+    pub trade_fee_bps: u16,
+    pub relist_fee_bps: u16,
+    pub treasury_bump: u8,
+    pub multisig_admins: Vec<Pubkey>,
+    pub multisig_threshold: u8,
+    pub transfer_fee_bps: u16,
+    pub trade_offer_fee_lamports: u64,
+    /// Share of trade fees routed to a payer's registered referrer
+    pub referral_share_bps: u16,
+}
+
+/// Upper bound on `referral_share_bps`: referrers get at most 5% of any
+/// trade fee, so the treasury keeps the bulk of every fee it is owed.
+pub const MAX_REFERRAL_SHARE_BPS: u16 = 500;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum FeeTierKind {
+    /// Discount for a specific wallet
+    Wallet,
+    /// Discount for any wallet holding `min_balance` of a mint (NFT or UOG)
+    Holding,
+    /// Discount for any wallet with a UOG stake position of at least `min_balance`
+    Staked,
+}
+
+#[account]
+pub struct FeeTier {
+    /// Wallet for `Wallet` tiers, mint for `Holding` and `Staked` tiers
+    pub subject: Pubkey,
+    pub kind: FeeTierKind,
+    pub min_balance: u64,
+    pub discount_bps: u16,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct CnftLeaf {
+    pub root: [u8; 32],
+    pub data_hash: [u8; 32],
+    pub creator_hash: [u8; 32],
+    pub nonce: u64,
+    pub index: u32,
+}
+
+#[account]
+pub struct HeroTree {
+    pub merkle_tree: Pubkey,
+    pub collection_mint: Pubkey,
+    pub minted: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct HeroMintConfig {
+    /// Hero collection: a Metaplex collection NFT, or a Token-2022 group mint
+    pub collection_mint: Pubkey,
+    /// Fee mint for primary hero mints (So111... for native SOL)
+    pub payment_mint: Pubkey,
+    /// Fixed primary mint fee, in `payment_mint` units
+    pub mint_fee: u64,
+    /// Bump of the `collection_authority` PDA, the collection's update authority
+    pub collection_authority_bump: u8,
+    pub bump: u8,
+}
+
+#[account]
+pub struct CnftListing {
+    pub seller: Pubkey,
+    pub merkle_tree: Pubkey,
+    pub asset_id: Pubkey,
+    pub price: u64,
+    pub use_sol: bool,
+    pub bump: u8,
+    /// Mint the price is denominated in (So111... for native SOL)
+    pub payment_mint: Pubkey,
+}
+
+#[account]
+pub struct AllowedCollection {
+    pub collection_mint: Pubkey,
+    pub bump: u8,
+}
+
+pub const MAX_BUNDLE_SIZE: usize = 5;
+
+#[account]
+pub struct BundleListing {
+    pub seller: Pubkey,
+    pub bundle_id: u64,
+    pub mints: Vec<Pubkey>,
+    pub price: u64,
+    pub use_sol: bool,
+    pub bump: u8,
+    /// Mint the price is denominated in (So111... for native SOL)
+    pub payment_mint: Pubkey,
+}
+
+pub const MAX_TRADE_OFFER_MINTS: usize = 3;
+
+#[account]
+pub struct TradeOffer {
+    pub maker: Pubkey,
+    pub offer_id: u64,
+    /// Only this wallet may accept, when set
+    pub taker: Option<Pubkey>,
+    pub offered_mints: Vec<Pubkey>,
+    pub wanted_mints: Vec<Pubkey>,
+    /// Lamports escrowed on the offer account on top of its rent
+    pub sol_amount: u64,
+    pub token_mint: Option<Pubkey>,
+    /// Sweetener held in the offer vault, net of token transfer fees
+    pub token_amount: u64,
+    pub bump: u8,
+}
+
+pub const SECONDS_PER_DAY: i64 = 86_400;
+
+#[account]
+pub struct RentalListing {
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub daily_price: u64,
+    pub max_days: u16,
+    pub use_sol: bool,
+    /// Wallet holding the usage right until `rented_until`
+    pub renter: Option<Pubkey>,
+    pub rented_until: i64,
+    pub bump: u8,
+    /// Mint the daily price is denominated in (So111... for native SOL)
+    pub payment_mint: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rarity {
+    Common,
+    Rare,
+    Epic,
+    Legendary,
+}
+
+impl Rarity {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "common" => Some(Rarity::Common),
+            "rare" => Some(Rarity::Rare),
+            "epic" => Some(Rarity::Epic),
+            "legendary" => Some(Rarity::Legendary),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rarity::Common => "Common",
+            Rarity::Rare => "Rare",
+            Rarity::Epic => "Epic",
+            Rarity::Legendary => "Legendary",
+        }
+    }
+}
+
+#[account]
+pub struct HeroRarity {
+    pub nft_mint: Pubkey,
+    pub rarity: Rarity,
+    pub bump: u8,
+}
+
+/// Fixed-point scale for `acc_reward_per_weight`.
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
+
+#[account]
+pub struct HeroStakingPool {
+    pub collection_mint: Pubkey,
+    pub reward_mint: Pubkey,
+    pub reward_vault: Pubkey,
+    /// Reward units emitted per second, shared across all staked weight
+    pub reward_rate_per_second: u64,
+    /// Weight per rarity, indexed by `Rarity`
+    pub rarity_weights: [u16; 4],
+    pub total_weight: u64,
+    pub acc_reward_per_weight: u128,
+    pub last_update_ts: i64,
+    pub bump: u8,
+}
+
+/// Advances a per-weight reward index by `rate_per_second` emitted since
+/// `last_update_ts`, shared pro rata across `total_weight`.
+pub fn accrue_reward_index(acc_reward_per_weight: &mut u128, last_update_ts: &mut i64, total_weight: u64, rate_per_second: u64, now: i64) -> Result<()> {
+    if now <= *last_update_ts {
+        return Ok(());
+    }
+    if total_weight > 0 {
+        let elapsed = (now - *last_update_ts) as u128;
+        let emitted = elapsed
+            .checked_mul(rate_per_second as u128)
+            .and_then(|v| v.checked_mul(REWARD_PRECISION))
+            .ok_or(MarketplaceError::MathOverflow)?;
+        *acc_reward_per_weight = acc_reward_per_weight
+            .checked_add(emitted / total_weight as u128)
+            .ok_or(MarketplaceError::MathOverflow)?;
+    }
+    *last_update_ts = now;
+    Ok(())
+}
+
+impl HeroStakingPool {
+    pub fn update_rewards(&mut self, now: i64) -> Result<()> {
+        accrue_reward_index(
+            &mut self.acc_reward_per_weight,
+            &mut self.last_update_ts,
+            self.total_weight,
+            self.reward_rate_per_second,
+            now,
+        )
+    }
+
+    pub fn accrued_for(&self, weight: u64) -> u128 {
+        self.acc_reward_per_weight.saturating_mul(weight as u128) / REWARD_PRECISION
+    }
+}
+
+#[account]
+pub struct HeroStake {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub weight: u64,
+    pub reward_debt: u128,
+    /// Rewards earned but not yet paid because the vault ran short
+    pub unpaid_rewards: u64,
+    pub staked_at: i64,
+    pub bump: u8,
+}
+
+/// Lockup length in days for each UOG staking tier.
+pub const UOG_LOCKUP_DAYS: [i64; 3] = [30, 90, 180];
+
+#[account]
+pub struct UogStakingPool {
+    pub uog_mint: Pubkey,
+    /// PDA token account holding staked principal
+    pub stake_vault: Pubkey,
+    /// PDA token account funded with UOG emissions
+    pub reward_vault: Pubkey,
+    pub reward_rate_per_second: u64,
+    /// Reward weight multiplier per lockup tier, 10_000 = 1x
+    pub boost_bps: [u16; 3],
+    pub early_withdraw_penalty_bps: u16,
+    pub total_staked: u64,
+    pub total_weight: u64,
+    pub acc_reward_per_weight: u128,
+    pub last_update_ts: i64,
+    pub bump: u8,
+    pub stake_vault_bump: u8,
+    pub reward_vault_bump: u8,
+}
+
+impl UogStakingPool {
+    pub fn update_rewards(&mut self, now: i64) -> Result<()> {
+        accrue_reward_index(
+            &mut self.acc_reward_per_weight,
+            &mut self.last_update_ts,
+            self.total_weight,
+            self.reward_rate_per_second,
+            now,
+        )
+    }
+
+    pub fn accrued_for(&self, weight: u64) -> u128 {
+        self.acc_reward_per_weight.saturating_mul(weight as u128) / REWARD_PRECISION
+    }
+}
+
+#[account]
+pub struct UogStake {
+    pub owner: Pubkey,
+    pub uog_mint: Pubkey,
+    pub tier: u8,
+    pub amount: u64,
+    pub weight: u64,
+    pub reward_debt: u128,
+    pub unpaid_rewards: u64,
+    pub unlock_ts: i64,
+    pub bump: u8,
+}
+
+pub const MAX_PACK_NAME_LEN: usize = 24;
+pub const MAX_PACK_SYMBOL_LEN: usize = 10;
+pub const MAX_PACK_URI_LEN: usize = 160;
+
+#[account]
+pub struct GatchaPack {
+    pub pack_id: u64,
+    pub price: u64,
+    pub use_sol: bool,
+    pub payment_mint: Pubkey,
+    /// Pull odds per rarity, indexed by `Rarity`
+    pub rarity_weights: [u16; 4],
+    pub supply: u64,
+    pub sold: u64,
+    pub opened: u64,
+    /// Minted heroes are named `"{hero_name} #{n}"`
+    pub hero_name: String,
+    pub symbol: String,
+    /// Minted heroes point at `"{base_uri}{rarity}.json"`
+    pub base_uri: String,
+    pub pity: PityRules,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct PityRules {
+    /// Pull N is guaranteed Epic or better after N-1 pulls without one; 0 disables
+    pub guaranteed_epic_within: u16,
+    /// Legendary odds are boosted after this many pulls without one; 0 disables
+    pub legendary_rate_up_after: u16,
+    /// Legendary weight multiplier while rate-up is active, 10_000 = 1x
+    pub legendary_rate_up_bps: u16,
+}
+
+#[account]
+pub struct PityCounter {
+    pub pack: Pubkey,
+    pub player: Pubkey,
+    pub total_pulls: u64,
+    pub pulls_since_epic: u16,
+    pub pulls_since_legendary: u16,
+    pub bump: u8,
+}
+
+/// Slots after purchase during which a pack can be revealed; kept inside the
+/// 512-entry SlotHashes window.
+pub const PACK_REVEAL_WINDOW_SLOTS: u64 = 500;
+
+#[account]
+pub struct PackPurchase {
+    pub pack: Pubkey,
+    pub buyer: Pubkey,
+    pub index: u64,
+    /// `hash(secret)` supplied by the buyer at purchase
+    pub commitment: [u8; 32],
+    pub price_paid: u64,
+    pub purchased_slot: u64,
+    pub bump: u8,
+}
+
+pub const MAX_PAYOUT_PLACES: usize = 10;
+/// Seconds after cancellation during which entrants and sponsors can claim
+/// refunds before the leftovers may be swept to the treasury.
+pub const TOURNAMENT_CLAIM_WINDOW_SECS: i64 = 30 * 24 * 60 * 60;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TournamentStatus {
+    Open,
+    Settled,
+    Cancelled,
+}
+
+/// Prize pool escrow. SOL pools are held as lamports on this account; SPL
+/// pools in `vault`, a token account owned by this PDA. Funds only leave
+/// through settlement.
+#[account]
+pub struct Tournament {
+    pub tournament_id: u64,
+    pub payment_mint: Pubkey,
+    pub use_sol: bool,
+    pub vault: Pubkey,
+    pub entry_fee: u64,
+    pub min_participants: u16,
+    pub max_participants: u16,
+    pub participant_count: u16,
+    /// Entry fees received, net of token transfer fees
+    pub entry_pool: u64,
+    pub sponsor_pool: u64,
+    pub registration_end_ts: i64,
+    /// Share of the prize pool per finishing place, summing to 10_000
+    pub payout_bps: Vec<u16>,
+    /// Treasury cut taken from entry fees (sponsor prizes are not cut)
+    pub house_cut_bps: u16,
+    /// Key whose Ed25519 signature over the standings settles the tournament
+    pub result_oracle: Pubkey,
+    pub status: TournamentStatus,
+    pub bump: u8,
+    /// When the tournament was cancelled; starts the refund claim window
+    pub cancelled_at: i64,
+}
+
+impl Tournament {
+    /// Cancels a tournament whose registration closed below
+    /// `min_participants`. Returns whether it did.
+    pub fn cancel_if_under_filled(&mut self, now: i64) -> bool {
+        let under_filled = self.status == TournamentStatus::Open
+            && now >= self.registration_end_ts
+            && self.participant_count < self.min_participants;
+        if under_filled {
+            self.status = TournamentStatus::Cancelled;
+            self.cancelled_at = self.registration_end_ts;
+        }
+        under_filled
+    }
+}
+
+#[account]
+pub struct TournamentParticipant {
+    pub tournament: Pubkey,
+    pub player: Pubkey,
+    pub entry_paid: u64,
+    pub bump: u8,
+}
+
+/// Sponsor contribution receipt, refunded if the tournament is cancelled.
+#[account]
+pub struct TournamentSponsor {
+    pub tournament: Pubkey,
+    pub sponsor: Pubkey,
+    /// Amount received, net of token transfer fees
+    pub amount: u64,
+    pub bump: u8,
+}
+
+/// Lowest tournament id `create_tournament` will still accept.
+#[account]
+pub struct TournamentCounter {
+    pub next_tournament_id: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct BattleConfig {
+    /// Key whose Ed25519 signature settles wager matches
+    pub battle_server: Pubkey,
+    pub wager_fee_bps: u16,
+    /// Seconds after acceptance before an unsettled wager can be withdrawn
+    pub result_timeout_secs: i64,
+    pub bump: u8,
+    /// Mint both wager stakes are deposited in
+    pub uog_mint: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum WagerStatus {
+    /// Creator has deposited, waiting for the opponent
+    Pending,
+    /// Both stakes are escrowed, waiting for a result
+    Active,
+    /// Result posted, payable once the dispute window passes
+    Resolved,
+}
+
+#[account]
+pub struct WagerMatch {
+    pub match_id: u64,
+    pub player_a: Pubkey,
+    pub player_b: Pubkey,
+    pub uog_mint: Pubkey,
+    /// PDA token account owning both stakes
+    pub vault: Pubkey,
+    pub stake_amount: u64,
+    /// Hero mints each side committed, held by that player at commit time
+    pub team_a: [Pubkey; battle::TEAM_SIZE],
+    pub team_b: [Pubkey; battle::TEAM_SIZE],
+    /// Registry rarity of each committed hero, which fixes its battle stats
+    pub team_a_rarity: [Rarity; battle::TEAM_SIZE],
+    pub team_b_rarity: [Rarity; battle::TEAM_SIZE],
+    pub status: WagerStatus,
+    pub accepted_at: i64,
+    pub bump: u8,
+    pub vault_bump: u8,
+    /// Slot of acceptance; part of the signed result so a result for an
+    /// earlier wager under the same `match_id` cannot be replayed
+    pub accepted_slot: u64,
+    /// Winner posted by the battle server
+    pub winner: Pubkey,
+    /// Seed the server battled with, signed alongside the result
+    pub battle_seed: [u8; 32],
+    pub resolved_at: i64,
+}
+
+/// Seconds after a posted result during which either player can dispute it.
+pub const WAGER_DISPUTE_WINDOW_SECS: i64 = 60 * 60;
+
+/// Bitmap of 8_000 bytes keeps the account under the 10KiB CPI init limit.
+pub const MAX_DISTRIBUTION_CLAIMS: u32 = 64_000;
+
+/// Epoch payout of daily / match earnings, claimable against `merkle_root`.
+#[account]
+pub struct Distribution {
+    pub epoch: u64,
+    pub merkle_root: [u8; 32],
+    pub uog_mint: Pubkey,
+    /// PDA token account owned by this distribution
+    pub vault: Pubkey,
+    pub total_amount: u64,
+    pub claimed_amount: u64,
+    pub num_claims: u32,
+    pub expires_at: i64,
+    /// One bit per leaf index, set once claimed
+    pub claimed_bitmap: Vec<u8>,
+    pub bump: u8,
+}
+
+/// Referral registry of a player: who referred them, and their own stats
+/// as a referrer.
+#[account]
+pub struct Referral {
+    pub player: Pubkey,
+    pub referrer: Option<Pubkey>,
+    pub referral_count: u32,
+    pub rewarded_trades: u64,
+    pub rewards_lamports: u64,
+    /// Token rewards in base units, summed across payment mints
+    pub rewards_tokens: u64,
+    pub bump: u8,
+}
+
+#[event]
+pub struct MarketInitialized {
+    pub admin: Pubkey,
+    pub mint_fee_bps: u16,
+    pub trade_fee_bps: u16,
+    pub relist_fee_bps: u16,
+    pub transfer_fee_bps: u16,
+    pub multisig_threshold: u8,
+}
+
+#[event]
+pub struct FeeTierSet {
+    pub subject: Pubkey,
+    pub kind: FeeTierKind,
+    pub min_balance: u64,
+    pub discount_bps: u16,
+}
+
+#[event]
+pub struct FeeTierRemoved {
+    pub subject: Pubkey,
+}
+
+#[event]
+pub struct NftMintedAndListed {
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub price: u64,
+    pub use_sol: bool,
+    pub mint_fee: u64,
+}
+
+#[event]
+pub struct NftSold {
+    pub nft_mint: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub price: u64,
+    pub fee: u64,
+    /// Amount the seller received after any Token-2022 transfer fee
+    pub seller_proceeds: u64,
+    pub use_sol: bool,
+}
+
+#[event]
+pub struct NftRelisted {
+    pub nft_mint: Pubkey,
+    pub seller: Pubkey,
+    pub price: u64,
+    pub use_sol: bool,
+    pub relist_fee: u64,
+}
+
+#[event]
+pub struct TokenSent {
+    pub sender: Pubkey,
+    pub recipient: Pubkey,
+    pub mint: Pubkey,
+    /// Amount received by the recipient, fee excluded
+    pub amount: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct TokenSwapped {
+    pub user: Pubkey,
+    pub dex_program: Pubkey,
+    pub output_mint: Pubkey,
+    /// Amount debited from the user's input account by the DEX
+    pub in_amount: u64,
+    pub out_amount: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct TreasuryWithdrawn {
+    pub mint: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct HeroTreeCreated {
+    pub merkle_tree: Pubkey,
+    pub collection_mint: Pubkey,
+    pub max_depth: u32,
+    pub max_buffer_size: u32,
+}
+
+#[event]
+pub struct CompressedHeroMinted {
+    pub merkle_tree: Pubkey,
+    pub asset_id: Pubkey,
+    pub leaf_owner: Pubkey,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct CnftListed {
+    pub asset_id: Pubkey,
+    pub seller: Pubkey,
+    pub price: u64,
+    pub use_sol: bool,
+}
+
+#[event]
+pub struct CnftSold {
+    pub asset_id: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub price: u64,
+    pub fee: u64,
+    pub use_sol: bool,
+}
+
+#[event]
+pub struct CnftListingCancelled {
+    pub asset_id: Pubkey,
+    pub seller: Pubkey,
+}
+
+#[event]
+pub struct HeroMinted {
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub use_sol: bool,
+    pub mint_fee: u64,
+}
+
+#[event]
+pub struct NftListed {
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub collection: Pubkey,
+    pub price: u64,
+    pub use_sol: bool,
+    pub listing_fee: u64,
+    pub mode: ListingMode,
+}
+
+#[event]
+pub struct CollectionAllowed {
+    pub collection_mint: Pubkey,
+}
+
+#[event]
+pub struct CollectionDisallowed {
+    pub collection_mint: Pubkey,
+}
+
+#[event]
+pub struct ListingCancelled {
+    pub nft_mint: Pubkey,
+    pub seller: Pubkey,
+    pub mode: ListingMode,
+}
+
+#[event]
+pub struct BundleListed {
+    pub bundle: Pubkey,
+    pub seller: Pubkey,
+    pub mints: Vec<Pubkey>,
+    pub price: u64,
+    pub use_sol: bool,
+    pub listing_fee: u64,
+}
+
+#[event]
+pub struct BundleSold {
+    pub bundle: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub mints: Vec<Pubkey>,
+    pub price: u64,
+    pub fee: u64,
+    pub use_sol: bool,
+}
+
+#[event]
+pub struct BundleCancelled {
+    pub bundle: Pubkey,
+    pub seller: Pubkey,
+}
+
+#[event]
+pub struct TradeOfferFeeUpdated {
+    pub trade_offer_fee_lamports: u64,
+}
+
+#[event]
+pub struct TradeOfferCreated {
+    pub offer: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Option<Pubkey>,
+    pub offered_mints: Vec<Pubkey>,
+    pub wanted_mints: Vec<Pubkey>,
+    pub sol_amount: u64,
+    pub token_mint: Option<Pubkey>,
+    pub token_amount: u64,
+}
+
+#[event]
+pub struct TradeOfferAccepted {
+    pub offer: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub fee: u64,
+}
+
+#[event]
+pub struct TradeOfferCancelled {
+    pub offer: Pubkey,
+    pub maker: Pubkey,
+}
+
+#[event]
+pub struct HeroListedForRent {
+    pub nft_mint: Pubkey,
+    pub owner: Pubkey,
+    pub daily_price: u64,
+    pub max_days: u16,
+    pub use_sol: bool,
+}
+
+#[event]
+pub struct HeroRented {
+    pub nft_mint: Pubkey,
+    pub owner: Pubkey,
+    pub renter: Pubkey,
+    pub days: u16,
+    pub price: u64,
+    pub fee: u64,
+    pub rented_until: i64,
+}
+
+#[event]
+pub struct RentalReclaimed {
+    pub nft_mint: Pubkey,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct HeroRaritySet {
+    pub nft_mint: Pubkey,
+    pub rarity: Rarity,
+}
+
+#[event]
+pub struct HeroStakingPoolCreated {
+    pub pool: Pubkey,
+    pub collection_mint: Pubkey,
+    pub reward_mint: Pubkey,
+    pub reward_rate_per_second: u64,
+}
+
+#[event]
+pub struct HeroStaked {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub rarity: Rarity,
+    pub weight: u64,
+}
+
+#[event]
+pub struct HeroRewardsClaimed {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct HeroUnstaked {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub rewards_paid: u64,
+    pub rewards_forfeited: u64,
+}
+
+#[event]
+pub struct UogStakingPoolCreated {
+    pub uog_mint: Pubkey,
+    pub reward_rate_per_second: u64,
+    pub boost_bps: [u16; 3],
+    pub early_withdraw_penalty_bps: u16,
+}
+
+#[event]
+pub struct UogStaked {
+    pub owner: Pubkey,
+    pub tier: u8,
+    pub amount: u64,
+    pub total_amount: u64,
+    pub unlock_ts: i64,
+}
+
+#[event]
+pub struct UogRewardsClaimed {
+    pub owner: Pubkey,
+    pub tier: u8,
+    pub amount: u64,
+}
+
+#[event]
+pub struct UogUnstaked {
+    pub owner: Pubkey,
+    pub tier: u8,
+    pub amount: u64,
+    pub rewards_paid: u64,
+    pub penalty: u64,
+}
+
+#[event]
+pub struct GatchaPackCreated {
+    pub pack: Pubkey,
+    pub pack_id: u64,
+    pub price: u64,
+    pub payment_mint: Pubkey,
+    pub rarity_weights: [u16; 4],
+    pub supply: u64,
+    pub pity: PityRules,
+}
+
+#[event]
+pub struct PackPurchased {
+    pub pack: Pubkey,
+    pub buyer: Pubkey,
+    pub index: u64,
+    pub price: u64,
+}
+
+#[event]
+pub struct PackOpened {
+    pub pack: Pubkey,
+    pub buyer: Pubkey,
+    pub nft_mint: Pubkey,
+    pub rarity: Rarity,
+    pub name: String,
+    /// Rarity was raised to Epic by the pity guarantee
+    pub pity_hit: bool,
+    /// Legendary odds were boosted for this pull
+    pub rate_up: bool,
+}
+
+#[event]
+pub struct TournamentCreated {
+    pub tournament: Pubkey,
+    pub tournament_id: u64,
+    pub payment_mint: Pubkey,
+    pub entry_fee: u64,
+    pub registration_end_ts: i64,
+}
+
+#[event]
+pub struct TournamentSponsored {
+    pub tournament: Pubkey,
+    pub sponsor: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct TournamentJoined {
+    pub tournament: Pubkey,
+    pub player: Pubkey,
+    pub entry_paid: u64,
+    pub participant_count: u16,
+}
+
+#[event]
+pub struct TournamentSettled {
+    pub tournament: Pubkey,
+    pub standings: Vec<Pubkey>,
+    pub prize_pool: u64,
+    pub house_cut: u64,
+}
+
+#[event]
+pub struct TournamentEntryClosed {
+    pub tournament: Pubkey,
+    pub player: Pubkey,
+}
+
+#[event]
+pub struct TournamentCancelled {
+    pub tournament: Pubkey,
+    pub participant_count: u16,
+}
+
+#[event]
+pub struct TournamentRefundClaimed {
+    pub tournament: Pubkey,
+    pub player: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct TournamentSponsorRefunded {
+    pub tournament: Pubkey,
+    pub sponsor: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct TournamentSponsorReceiptClosed {
+    pub tournament: Pubkey,
+    pub sponsor: Pubkey,
+}
+
+#[event]
+pub struct TournamentSwept {
+    pub tournament: Pubkey,
+    pub swept: u64,
+}
+
+#[event]
+pub struct BattleConfigUpdated {
+    pub battle_server: Pubkey,
+    pub wager_fee_bps: u16,
+    pub result_timeout_secs: i64,
+    pub uog_mint: Pubkey,
+}
+
+#[event]
+pub struct WagerCreated {
+    pub wager: Pubkey,
+    pub match_id: u64,
+    pub player_a: Pubkey,
+    pub player_b: Pubkey,
+    pub stake_amount: u64,
+}
+
+#[event]
+pub struct WagerAccepted {
+    pub wager: Pubkey,
+    pub player_b: Pubkey,
+}
+
+#[event]
+pub struct WagerCancelled {
+    pub wager: Pubkey,
+}
+
+#[event]
+pub struct WagerSettled {
+    pub wager: Pubkey,
+    pub winner: Pubkey,
+    pub payout: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct WagerExpired {
+    pub wager: Pubkey,
+}
+
+#[event]
+pub struct WagerResultPosted {
+    pub wager: Pubkey,
+    pub winner: Pubkey,
+    pub resolved_at: i64,
+}
+
+#[event]
+pub struct WagerDisputeResolved {
+    pub wager: Pubkey,
+    /// `Pubkey::default()` for a draw
+    pub winner: Pubkey,
+    pub payout: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct DistributionCreated {
+    pub distribution: Pubkey,
+    pub epoch: u64,
+    pub merkle_root: [u8; 32],
+    pub total_amount: u64,
+    pub num_claims: u32,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct EarningsClaimed {
+    pub distribution: Pubkey,
+    pub claimant: Pubkey,
+    pub index: u32,
+    pub amount: u64,
+}
+
+#[event]
+pub struct DistributionSwept {
+    pub distribution: Pubkey,
+    pub epoch: u64,
+    pub swept: u64,
+}
+
+#[event]
+pub struct ReferralShareUpdated {
+    pub referral_share_bps: u16,
+}
+
+#[event]
+pub struct ReferrerRegistered {
+    pub player: Pubkey,
+    pub referrer: Pubkey,
+    pub referral_count: u32,
+}
+
+#[event]
+pub struct ReferralRewarded {
+    pub referrer: Pubkey,
+    pub player: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct MarketConfigMigrated {
+    pub previous_len: u32,
+    pub new_len: u32,
+}
+
+#[event]
+pub struct HeroMintConfigUpdated {
+    pub collection_mint: Pubkey,
+    pub collection_authority: Pubkey,
+    pub payment_mint: Pubkey,
+    pub mint_fee: u64,
+}
+
+#[error_code]
+pub enum MarketplaceError {
+    #[msg("Invalid Owner")]
+    InvalidOwner,
+    #[msg("Invalid NFT")]
+    InvalidNFT,    
+    #[msg("Math Over flow")]
+    MathOverflow,
+    #[msg("InvalidThreshold")]
+    InvalidThreshold,
+    #[msg("Unauthorized action")]
+    Unauthorized,
+    #[msg("Fee bps must not exceed 10000")]
+    InvalidFeeBps,
+    #[msg("Fee tier does not apply to this payer")]
+    InvalidFeeTier,
+    #[msg("Payment mint or token program missing or invalid")]
+    InvalidMint,
+    #[msg("Token Metadata account required for pNFT transfer is missing")]
+    MissingPnftAccount,
+    #[msg("NFT is not part of an allowed collection")]
+    CollectionNotAllowed,
+    #[msg("Computed fee exceeds the caller's maximum")]
+    FeeExceedsMax,
+    #[msg("Escrow vault required for custody listings")]
+    MissingEscrowVault,
+    #[msg("Bundle accounts do not match the listed mints")]
+    InvalidBundle,
+    #[msg("Trade offer accounts do not match the offer")]
+    InvalidTradeOffer,
+    #[msg("Rental duration out of range")]
+    InvalidRentalDuration,
+    #[msg("Hero is currently rented")]
+    RentalActive,
+    #[msg("Invalid staking pool configuration")]
+    InvalidStakingConfig,
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Invalid lockup tier")]
+    InvalidLockupTier,
+    #[msg("Stake is still locked")]
+    StakeLocked,
+    #[msg("Invalid gatcha pack configuration")]
+    InvalidGatchaPack,
+    #[msg("Gatcha pack is sold out")]
+    PackSoldOut,
+    #[msg("Pack cannot be opened in the purchase slot")]
+    PackNotReady,
+    #[msg("Revealed secret does not match the commitment")]
+    InvalidReveal,
+    #[msg("Reveal window has expired")]
+    RevealExpired,
+    #[msg("Invalid tournament configuration")]
+    InvalidTournament,
+    #[msg("Tournament is not open for this action")]
+    TournamentClosed,
+    #[msg("Tournament is full")]
+    TournamentFull,
+    #[msg("Missing or invalid Ed25519 signature")]
+    InvalidSignature,
+    #[msg("Wager is not in a valid state for this action")]
+    InvalidWager,
+    #[msg("Wager result timeout has not elapsed")]
+    WagerNotExpired,
+    #[msg("Wager dispute window is still open")]
+    DisputeWindowOpen,
+    #[msg("Wager dispute window has closed")]
+    DisputeWindowClosed,
+    #[msg("Invalid distribution configuration")]
+    InvalidDistribution,
+    #[msg("Distribution has expired")]
+    DistributionExpired,
+    #[msg("Distribution has not expired yet")]
+    DistributionActive,
+    #[msg("Invalid merkle proof")]
+    InvalidProof,
+    #[msg("Earnings already claimed")]
+    AlreadyClaimed,
+    #[msg("Referral accounts do not match the payer's referrer")]
+    InvalidReferral,
+    #[msg("Referrer already registered")]
+    ReferrerAlreadySet,
+    #[msg("Market config account has an unexpected layout")]
+    InvalidMarketConfig,
+    #[msg("Listing mode does not allow this action")]
+    InvalidListingMode,
+    #[msg("NFT already has an active listing")]
+    AlreadyListed,
+    #[msg("Refund claim window is still open")]
+    ClaimWindowOpen,
+}
+
+#[derive(Accounts)]
+pub struct InitializeMarket<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = MARKET_CONFIG_SPACE,
+        seeds = [b"market_config"],
+        bump
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(seeds = [b"treasury"], bump)]
+    /// CHECK: only used as a PDA to store SOL/SPL
+    pub treasury_pda: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateMarketConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"market_config"],
+        bump,
+        owner = crate::ID
+    )]
+    /// CHECK: decoded by hand in migrate_market_config; old layouts do not deserialize as MarketConfig
+    pub market_config: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(subject: Pubkey)]
+pub struct CreateFeeTier<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 1 + 8 + 2 + 1,
+        seeds = [b"fee_tier", subject.as_ref()],
+        bump
+    )]
+    pub fee_tier: Account<'info, FeeTier>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFeeTier<'info> {
+    #[account(
+        mut,
+        seeds = [b"fee_tier", fee_tier.subject.as_ref()],
+        bump = fee_tier.bump
+    )]
+    pub fee_tier: Account<'info, FeeTier>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveFeeTier<'info> {
+    #[account(
+        mut,
+        seeds = [b"fee_tier", fee_tier.subject.as_ref()],
+        bump = fee_tier.bump,
+        close = admin
+    )]
+    pub fee_tier: Account<'info, FeeTier>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(collection_mint: Pubkey)]
+pub struct AddAllowedCollection<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 1,
+        seeds = [b"allowed_collection", collection_mint.as_ref()],
+        bump
+    )]
+    pub allowed_collection: Account<'info, AllowedCollection>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveAllowedCollection<'info> {
+    #[account(
+        mut,
+        seeds = [b"allowed_collection", allowed_collection.collection_mint.as_ref()],
+        bump = allowed_collection.bump,
+        close = admin
+    )]
+    pub allowed_collection: Account<'info, AllowedCollection>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetTradeOfferFee<'info> {
+    #[account(
+        mut,
+        seeds = [b"market_config"],
+        bump,
+        has_one = admin
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8,
+        seeds = [b"treasury"],
+        bump
+    )]
+    /// CHECK: only stores lamports
+    pub treasury_pda: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MintAndList<'info> {
+    #[account(
+        init,
+        payer = seller,
+        space = LISTING_SPACE,
+        seeds = [b"listing", mint.key().as_ref()],
+        bump
+    )]
+    pub listing: Account<'info, Listing>,
+
+    #[account(
+        seeds = [b"escrow_signer", mint.key().as_ref()],
+        bump
+    )]
+    /// CHECK: PDA escrow signer for ATA approval
+    pub escrow_signer: UncheckedAccount<'info>,
+
+    /// Seller: signer
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(mut)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: Seller NFT ATA
+    #[account(mut)]
+    pub seller_nft_ata: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"mint_auth", mint.key().as_ref()],
+        bump
+    )]
+    /// CHECK: PDA authority for mint
+    pub mint_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: PDA treasury to store lamports or SPL
+    pub treasury_pda: UncheckedAccount<'info>,
+
+    /// CHECK: Mint of payment SOL or SPL
+    pub payment_mint: UncheckedAccount<'info>,
+
+    /// Mint SPL for fee
+    pub spl_mint: InterfaceAccount<'info, Mint>,
+
+    /// Treasury token account receiving SPL payments
+    #[account(mut, token::mint = spl_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Seller token account paying SPL payments
+    #[account(mut, token::mint = spl_mint, token::authority = seller)]
+    pub seller_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// CHECK: Metadata PDA of mint
+    #[account(mut)]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: Metaplex Metadata Program
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    /// CHECK: Update authority for metadata
+    pub update_authority: UncheckedAccount<'info>,
+
+    /// Token program owning the NFT mint
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Token program owning spl_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: Rent sysvar
+    pub rent: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct BuyNft<'info> {
+    #[account(mut, has_one = seller, close = seller)]
+    pub listing: Account<'info, Listing>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: This is the seller wallet account.
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+
+    /// Buyer payment token account, required for SPL listings
+    #[account(mut, token::mint = payment_mint, token::authority = buyer)]
+    pub buyer_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Seller payment token account, required for SPL listings
+    #[account(mut, token::mint = payment_mint, token::authority = seller)]
+    pub seller_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Treasury payment token account, required for SPL listings
+    #[account(mut, token::mint = payment_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK:
+    pub treasury_pda: AccountInfo<'info>,
+
+    /// Seller NFT ATA, required for delegate-mode listings
+    #[account(mut, token::mint = nft_mint, token::authority = seller)]
+    pub seller_nft_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub buyer_nft_ata: InterfaceAccount<'info, TokenAccount>,
+
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(address = listing.nft_mint @ MarketplaceError::InvalidNFT)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+
+    /// Payment mint recorded on the listing (So111... for native SOL)
+    #[account(address = listing.payment_mint @ MarketplaceError::InvalidMint)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
+
+    /// CHECK: add seed bump
+    #[account(
+        mut,
+        seeds = [b"escrow_signer", listing.nft_mint.as_ref()],
+        bump
+    )]
+    /// CHECK: Escrow signer PDA authorized for NFT transfer
+    pub escrow_signer: AccountInfo<'info>,
+
+    /// Vault holding the NFT, required for custody-mode listings
+    #[account(
+        mut,
+        token::mint = nft_mint,
+        token::authority = escrow_signer
+    )]
+    pub escrow_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Optional fee discount for the buyer
+    pub fee_tier: Option<Account<'info, FeeTier>>,
+
+    /// Token account proving a holding-based fee tier
+    pub fee_tier_holding: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// UOG stake position proving a staking-based fee tier
+    pub fee_tier_stake: Option<Account<'info, UogStake>>,
+
+    /// Payer's referral registry, when they were referred
+    #[account(seeds = [b"referral", buyer.key().as_ref()], bump = referral.bump)]
+    pub referral: Option<Account<'info, Referral>>,
+
+    /// Referrer's registry, credited with the referral stats
+    #[account(
+        mut,
+        seeds = [b"referral", referrer_stats.player.as_ref()],
+        bump = referrer_stats.bump
+    )]
+    pub referrer_stats: Option<Account<'info, Referral>>,
+
+    /// CHECK: Referrer wallet (SOL) or token account (SPL), checked in pay_referral_share
+    #[account(mut)]
+    pub referrer_destination: Option<UncheckedAccount<'info>>,
+
+    /// Token Metadata accounts, required when the listed mint is a pNFT
+    pub pnft: PnftAccounts<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasury<'info> {
+    #[account(mut, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// CHECK: PDA treasury (authority)
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    pub treasury_pda: AccountInfo<'info>,
+
+    /// SPL mint (UOG, USDC, etc) or So111...
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Token account treasury_pda
+    #[account(mut)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token account admin
+    #[account(mut)]
+    pub admin_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Optional: legacy admin mode
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SendToken<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+
+    /// CHECK: raw recipient wallet, bound to recipient_token_account owner on the SPL path
+    #[account(mut)]
+    pub recipient: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (SOL & ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    /// Token mint (So111... for native SOL)
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = sender
+    )]
+    pub sender_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = recipient
+    )]
+    pub recipient_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = treasury_pda
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Market config
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// Optional fee discount for the sender
+    pub fee_tier: Option<Account<'info, FeeTier>>,
+
+    /// Token account proving a holding-based fee tier
+    pub fee_tier_holding: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// UOG stake position proving a staking-based fee tier
+    pub fee_tier_stake: Option<Account<'info, UogStake>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RelistNft<'info> {
+    #[account(
+        mut,
+        seeds = [b"listing", mint.key().as_ref()],
+        bump = listing.bump,
+        constraint = listing.nft_mint == mint.key() @ MarketplaceError::InvalidNFT,
+        // A custodied NFT is not in anyone's wallet; only its seller may cancel it
+        constraint = listing.mode == ListingMode::Delegate @ MarketplaceError::InvalidListingMode
+    )]
+    pub listing: Account<'info, Listing>,
+
+    #[account(mut)]
+    pub new_owner: Signer<'info>,
+
+    #[account(mut)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = seller_nft_ata.mint == mint.key() @ MarketplaceError::InvalidNFT,
+        constraint = seller_nft_ata.owner == new_owner.key() @ MarketplaceError::InvalidOwner,
+        constraint = seller_nft_ata.amount == 1 @ MarketplaceError::InvalidNFT,
+    )]
+    pub seller_nft_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// Relist fee mint (So111... for native SOL)
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = new_owner)]
+    pub seller_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (SOL & ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
+
+    #[account(
+        seeds = [b"escrow_signer", mint.key().as_ref()],
+        bump
+    )]
+    /// CHECK: PDA delegate authorized for NFT transfer
+    pub escrow_signer: UncheckedAccount<'info>,
+
+    /// Token Metadata accounts, required when the listed mint is a pNFT
+    pub pnft: PnftAccounts<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PnftAccounts<'info> {
+    /// CHECK: Metadata PDA of the NFT, verified in is_programmable
+    #[account(mut)]
+    pub metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Master edition PDA of the NFT, verified by Token Metadata
+    pub edition: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Token record of the current holder, verified by Token Metadata
+    #[account(mut)]
+    pub owner_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Token record of the receiving token account, verified by Token Metadata
+    #[account(mut)]
+    pub destination_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Rule set of the pNFT, enforced by Token Metadata
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Token Auth Rules program
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID)]
+    pub token_metadata_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+}
+
+impl<'info> PnftAccounts<'info> {
+    /// Whether `mint` is a programmable NFT. Without a metadata account the
+    /// mint is treated as a legacy NFT and moved with the escrow delegate.
+    pub fn is_programmable(&self, mint: &Pubkey) -> Result<bool> {
+        let metadata = match &self.metadata {
+            Some(metadata) => metadata,
+            None => return Ok(false),
+        };
+        let (expected, _) = MplMetadata::find_pda(mint);
+        require_keys_eq!(metadata.key(), expected, MarketplaceError::InvalidNFT);
+
+        let data = metadata.try_borrow_data()?;
+        let metadata = MplMetadata::safe_deserialize(&data)
+            .map_err(|_| MarketplaceError::InvalidNFT)?;
+        Ok(matches!(
+            metadata.token_standard,
+            Some(TokenStandard::ProgrammableNonFungible)
+                | Some(TokenStandard::ProgrammableNonFungibleEdition)
+        ))
+    }
+
+    /// Approves `delegate` as the Token Metadata transfer delegate of `token`.
+    pub fn delegate_transfer(&self, delegate: &AccountInfo<'info>, mint: &AccountInfo<'info>, token: &AccountInfo<'info>, owner: &AccountInfo<'info>, system_program: &AccountInfo<'info>, token_program: &AccountInfo<'info>) -> Result<()> {
+        let program = self.required(&self.token_metadata_program)?;
+        let metadata = self.required(&self.metadata)?;
+        let edition = self.required(&self.edition)?;
+        let owner_token_record = self.required(&self.owner_token_record)?;
+        let sysvar_instructions = self.required(&self.sysvar_instructions)?;
+        let rules_program = self.authorization_rules_program.as_ref().map(|a| a.to_account_info());
+        let rules = self.authorization_rules.as_ref().map(|a| a.to_account_info());
+
+        DelegateTransferV1CpiBuilder::new(&program)
+            .delegate(delegate)
+            .metadata(&metadata)
+            .master_edition(Some(&edition))
+            .token_record(Some(&owner_token_record))
+            .mint(mint)
+            .token(token)
+            .authority(owner)
+            .payer(owner)
+            .system_program(system_program)
+            .sysvar_instructions(&sysvar_instructions)
+            .spl_token_program(Some(token_program))
+            .authorization_rules_program(rules_program.as_ref())
+            .authorization_rules(rules.as_ref())
+            .amount(1)
+            .invoke()?;
+
+        Ok(())
+    }
+
+    /// Removes `delegate` as the Token Metadata transfer delegate of `token`.
+    pub fn revoke_transfer(&self, delegate: &AccountInfo<'info>, mint: &AccountInfo<'info>, token: &AccountInfo<'info>, owner: &AccountInfo<'info>, system_program: &AccountInfo<'info>, token_program: &AccountInfo<'info>) -> Result<()> {
+        let program = self.required(&self.token_metadata_program)?;
+        let metadata = self.required(&self.metadata)?;
+        let edition = self.required(&self.edition)?;
+        let owner_token_record = self.required(&self.owner_token_record)?;
+        let sysvar_instructions = self.required(&self.sysvar_instructions)?;
+        let rules_program = self.authorization_rules_program.as_ref().map(|a| a.to_account_info());
+        let rules = self.authorization_rules.as_ref().map(|a| a.to_account_info());
+
+        RevokeTransferV1CpiBuilder::new(&program)
+            .delegate(delegate)
+            .metadata(&metadata)
+            .master_edition(Some(&edition))
+            .token_record(Some(&owner_token_record))
+            .mint(mint)
+            .token(token)
+            .authority(owner)
+            .payer(owner)
+            .system_program(system_program)
+            .sysvar_instructions(&sysvar_instructions)
+            .spl_token_program(Some(token_program))
+            .authorization_rules_program(rules_program.as_ref())
+            .authorization_rules(rules.as_ref())
+            .invoke()?;
+
+        Ok(())
+    }
+
+    /// Moves a pNFT with Token Metadata `TransferV1`, signed by `authority`
+    /// (the escrow signer acting as transfer delegate, or the vault owner).
+    #[allow(clippy::too_many_arguments)]
+    pub fn transfer(&self, token: &AccountInfo<'info>, token_owner: &AccountInfo<'info>, destination_token: &AccountInfo<'info>, destination_owner: &AccountInfo<'info>, mint: &AccountInfo<'info>, authority: &AccountInfo<'info>, payer: &AccountInfo<'info>, system_program: &AccountInfo<'info>, token_program: &AccountInfo<'info>, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        let program = self.required(&self.token_metadata_program)?;
+        let metadata = self.required(&self.metadata)?;
+        let edition = self.required(&self.edition)?;
+        let owner_token_record = self.required(&self.owner_token_record)?;
+        let destination_token_record = self.required(&self.destination_token_record)?;
+        let sysvar_instructions = self.required(&self.sysvar_instructions)?;
+        let ata_program = self
+            .associated_token_program
+            .as_ref()
+            .ok_or(MarketplaceError::MissingPnftAccount)?
+            .to_account_info();
+        let rules_program = self.authorization_rules_program.as_ref().map(|a| a.to_account_info());
+        let rules = self.authorization_rules.as_ref().map(|a| a.to_account_info());
+
+        TransferV1CpiBuilder::new(&program)
+            .token(token)
+            .token_owner(token_owner)
+            .destination_token(destination_token)
+            .destination_owner(destination_owner)
+            .mint(mint)
+            .metadata(&metadata)
+            .edition(Some(&edition))
+            .token_record(Some(&owner_token_record))
+            .destination_token_record(Some(&destination_token_record))
+            .authority(authority)
+            .payer(payer)
+            .system_program(system_program)
+            .sysvar_instructions(&sysvar_instructions)
+            .spl_token_program(token_program)
+            .spl_ata_program(&ata_program)
+            .authorization_rules_program(rules_program.as_ref())
+            .authorization_rules(rules.as_ref())
+            .amount(1)
+            .invoke_signed(signer_seeds)?;
+
+        Ok(())
+    }
+
+    fn required(&self, account: &Option<UncheckedAccount<'info>>) -> Result<AccountInfo<'info>> {
+        account
+            .as_ref()
+            .map(|a| a.to_account_info())
+            .ok_or(MarketplaceError::MissingPnftAccount.into())
+    }
+}
+
+#[derive(Accounts)]
+pub struct BubblegumAccounts<'info> {
+    /// CHECK: Bubblegum tree config PDA, verified by Bubblegum
+    #[account(mut)]
+    pub tree_config: UncheckedAccount<'info>,
+
+    /// CHECK: Concurrent merkle tree, verified by Bubblegum
+    #[account(mut)]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Noop program
+    #[account(address = spl_noop::ID)]
+    pub log_wrapper: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Account Compression program
+    #[account(address = spl_account_compression::ID)]
+    pub compression_program: UncheckedAccount<'info>,
+
+    /// CHECK: Metaplex Bubblegum program
+    #[account(address = mpl_bubblegum::ID)]
+    pub bubblegum_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateHeroTree<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 32 + 8 + 1,
+        seeds = [b"hero_tree", merkle_tree.key().as_ref()],
+        bump
+    )]
+    pub hero_tree: Account<'info, HeroTree>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// CHECK: Bubblegum tree config PDA, created by Bubblegum
+    #[account(mut)]
+    pub tree_config: UncheckedAccount<'info>,
+
+    /// CHECK: Pre-allocated concurrent merkle tree, initialized by Bubblegum
+    #[account(mut)]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    /// Hero collection NFT
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: SPL Noop program
+    #[account(address = spl_noop::ID)]
+    pub log_wrapper: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Account Compression program
+    #[account(address = spl_account_compression::ID)]
+    pub compression_program: UncheckedAccount<'info>,
+
+    /// CHECK: Metaplex Bubblegum program
+    #[account(address = mpl_bubblegum::ID)]
+    pub bubblegum_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MintCompressedHero<'info> {
+    #[account(
+        mut,
+        seeds = [b"hero_tree", merkle_tree.key().as_ref()],
+        bump = hero_tree.bump,
+        has_one = merkle_tree,
+        has_one = collection_mint
+    )]
+    pub hero_tree: Account<'info, HeroTree>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// CHECK: Player receiving the compressed hero
+    pub leaf_owner: UncheckedAccount<'info>,
+
+    /// CHECK: Bubblegum tree config PDA, verified by Bubblegum
+    #[account(mut)]
+    pub tree_config: UncheckedAccount<'info>,
+
+    /// CHECK: Concurrent merkle tree, bound to hero_tree
+    #[account(mut)]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    /// Hero collection NFT
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: Collection metadata, verified by Bubblegum
+    #[account(mut)]
+    pub collection_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: Collection master edition, verified by Bubblegum
+    pub collection_edition: UncheckedAccount<'info>,
+
+    /// CHECK: Collection authority record delegating to hero_tree
+    pub collection_authority_record: UncheckedAccount<'info>,
+
+    /// CHECK: Bubblegum collection CPI signer PDA
+    pub bubblegum_signer: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Noop program
+    #[account(address = spl_noop::ID)]
+    pub log_wrapper: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Account Compression program
+    #[account(address = spl_account_compression::ID)]
+    pub compression_program: UncheckedAccount<'info>,
+
+    /// CHECK: Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    /// CHECK: Metaplex Bubblegum program
+    #[account(address = mpl_bubblegum::ID)]
+    pub bubblegum_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(leaf: CnftLeaf)]
+pub struct ListCnft<'info> {
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + 32 + 32 + 32 + 8 + 1 + 1 + 32,
+        seeds = [b"cnft_listing", get_asset_id(&bubblegum.merkle_tree.key(), leaf.nonce).as_ref()],
+        bump
+    )]
+    pub listing: Account<'info, CnftListing>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    /// Only leaves of the program's hero trees can be listed
+    #[account(
+        seeds = [b"hero_tree", bubblegum.merkle_tree.key().as_ref()],
+        bump = hero_tree.bump
+    )]
+    pub hero_tree: Account<'info, HeroTree>,
+
+    /// Payment mint of the listing (So111... for native SOL)
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds = [b"escrow_signer", get_asset_id(&bubblegum.merkle_tree.key(), leaf.nonce).as_ref()],
+        bump
+    )]
+    /// CHECK: PDA leaf delegate authorized for cNFT transfer
+    pub escrow_signer: UncheckedAccount<'info>,
+
+    pub bubblegum: BubblegumAccounts<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyCnft<'info> {
+    #[account(
+        mut,
+        seeds = [b"cnft_listing", listing.asset_id.as_ref()],
+        bump = listing.bump,
+        has_one = seller,
+        constraint = listing.merkle_tree == bubblegum.merkle_tree.key() @ MarketplaceError::InvalidNFT,
+        close = seller
+    )]
+    pub listing: Account<'info, CnftListing>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: Seller wallet, bound to listing.seller
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+
+    /// Payment mint recorded on the listing (So111... for native SOL)
+    #[account(address = listing.payment_mint @ MarketplaceError::InvalidMint)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = buyer)]
+    pub buyer_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = seller)]
+    pub seller_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (SOL & ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(
+        seeds = [b"escrow_signer", listing.asset_id.as_ref()],
+        bump
+    )]
+    /// CHECK: PDA leaf delegate authorized for cNFT transfer
+    pub escrow_signer: UncheckedAccount<'info>,
+
+    /// Optional fee discount for the buyer
+    pub fee_tier: Option<Account<'info, FeeTier>>,
+
+    /// Token account proving a holding-based fee tier
+    pub fee_tier_holding: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// UOG stake position proving a staking-based fee tier
+    pub fee_tier_stake: Option<Account<'info, UogStake>>,
+
+    pub bubblegum: BubblegumAccounts<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelCnftListing<'info> {
+    #[account(
+        mut,
+        seeds = [b"cnft_listing", listing.asset_id.as_ref()],
+        bump = listing.bump,
+        has_one = seller,
+        constraint = listing.merkle_tree == bubblegum.merkle_tree.key() @ MarketplaceError::InvalidNFT,
+        close = seller
+    )]
+    pub listing: Account<'info, CnftListing>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        seeds = [b"escrow_signer", listing.asset_id.as_ref()],
+        bump
+    )]
+    /// CHECK: Current leaf delegate being revoked
+    pub escrow_signer: UncheckedAccount<'info>,
+
+    pub bubblegum: BubblegumAccounts<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseStaleCnftListing<'info> {
+    #[account(
+        mut,
+        seeds = [b"cnft_listing", listing.asset_id.as_ref()],
+        bump = listing.bump,
+        has_one = seller,
+        constraint = listing.merkle_tree == bubblegum.merkle_tree.key() @ MarketplaceError::InvalidNFT,
+        close = seller
+    )]
+    pub listing: Account<'info, CnftListing>,
+
+    /// CHECK: Seller wallet receiving the listing rent, bound to listing.seller
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+
+    /// Seller, or the asset's current owner proving ownership through Bubblegum
+    pub closer: Signer<'info>,
+
+    pub bubblegum: BubblegumAccounts<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetHeroMintConfig<'info> {
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + 32 + 32 + 8 + 1 + 1,
+        seeds = [b"hero_mint_config"],
+        bump
+    )]
+    pub hero_mint_config: Account<'info, HeroMintConfig>,
+
+    #[account(seeds = [b"collection_authority"], bump)]
+    /// CHECK: PDA that must hold update authority over the hero collection
+    pub collection_authority: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct HeroCollectionAccounts<'info> {
+    #[account(seeds = [b"hero_mint_config"], bump = hero_mint_config.bump)]
+    pub hero_mint_config: Account<'info, HeroMintConfig>,
+
+    /// CHECK: Hero collection mint, bound to the hero mint config
+    #[account(
+        mut,
+        address = hero_mint_config.collection_mint @ MarketplaceError::CollectionNotAllowed
+    )]
+    pub collection_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Metadata PDA of the collection, verified by Token Metadata
+    #[account(mut)]
+    pub collection_metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Master edition of the collection, verified by Token Metadata
+    pub collection_master_edition: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [b"collection_authority"],
+        bump = hero_mint_config.collection_authority_bump
+    )]
+    /// CHECK: PDA update authority of the hero collection
+    pub collection_authority: UncheckedAccount<'info>,
+
+    /// CHECK: Instructions sysvar, required for Metaplex collections
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+}
+
+impl<'info> HeroCollectionAccounts<'info> {
+    /// Adds a freshly minted hero to the hero collection as a verified item:
+    /// a Token-2022 group member when the mint points at itself for its
+    /// membership, otherwise the Metaplex collection set on its metadata.
+    /// Must run while `mint_authority` still holds the mint authority.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_hero(&self, mint: &InterfaceAccount<'info, Mint>, mint_authority: &AccountInfo<'info>, mint_auth_seeds: &[&[u8]], metadata: &AccountInfo<'info>, token_metadata_program: &AccountInfo<'info>, payer: &AccountInfo<'info>, system_program: &AccountInfo<'info>) -> Result<()> {
+        let authority_seeds: &[&[u8]] = &[
+            b"collection_authority",
+            &[self.hero_mint_config.collection_authority_bump],
+        ];
+
+        if has_embedded_group_member(mint)? {
+            fund_mint_growth(
+                payer,
+                &mint.to_account_info(),
+                system_program,
+                4 + std::mem::size_of::<TokenGroupMember>(),
+            )?;
+
+            invoke_signed(
+                &spl_token_group_interface::instruction::initialize_member(
+                    &token_2022::ID,
+                    &mint.key(),
+                    &mint.key(),
+                    &mint_authority.key(),
+                    &self.collection_mint.key(),
+                    &self.collection_authority.key(),
+                ),
+                &[
+                    mint.to_account_info(),
+                    mint_authority.clone(),
+                    self.collection_mint.to_account_info(),
+                    self.collection_authority.to_account_info(),
+                ],
+                &[mint_auth_seeds, authority_seeds],
+            )?;
+        } else {
+            let collection_metadata = self
+                .collection_metadata
+                .as_ref()
+                .ok_or(MarketplaceError::CollectionNotAllowed)?
+                .to_account_info();
+            let collection_master_edition = self
+                .collection_master_edition
+                .as_ref()
+                .ok_or(MarketplaceError::CollectionNotAllowed)?
+                .to_account_info();
+            let sysvar_instructions = self
+                .sysvar_instructions
+                .as_ref()
+                .ok_or(MarketplaceError::MissingPnftAccount)?
+                .to_account_info();
+
+            VerifyCollectionV1CpiBuilder::new(token_metadata_program)
+                .authority(&self.collection_authority.to_account_info())
+                .metadata(metadata)
+                .collection_mint(&self.collection_mint.to_account_info())
+                .collection_metadata(Some(&collection_metadata))
+                .collection_master_edition(Some(&collection_master_edition))
+                .system_program(system_program)
+                .sysvar_instructions(&sysvar_instructions)
+                .invoke_signed(&[authority_seeds])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct MintHero<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = mint.supply == 0 && mint.decimals == 0 @ MarketplaceError::InvalidNFT,
+        mint::authority = mint_authority
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner
+    )]
+    pub owner_nft_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"mint_auth", mint.key().as_ref()],
+        bump
+    )]
+    /// CHECK: PDA authority for mint
+    pub mint_authority: UncheckedAccount<'info>,
+
+    /// CHECK: Metadata PDA of mint, verified against the mint for legacy NFTs
+    #[account(mut)]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: Metaplex Metadata Program
+    #[account(address = mpl_token_metadata::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    /// CHECK: Update authority for metadata, must be the market admin
+    #[account(address = market_config.admin @ MarketplaceError::Unauthorized)]
+    pub update_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (SOL & ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    /// Fee mint set in the hero mint config (So111... for native SOL)
+    #[account(address = hero_collection.hero_mint_config.payment_mint @ MarketplaceError::InvalidMint)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = owner)]
+    pub owner_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// Token program owning the NFT mint
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Hero collection the new mint is added to and verified in
+    pub hero_collection: HeroCollectionAccounts<'info>,
+
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ListNft<'info> {
+    #[account(
+        init,
+        payer = seller,
+        space = LISTING_SPACE,
+        seeds = [b"listing", mint.key().as_ref()],
+        bump
+    )]
+    pub listing: Account<'info, Listing>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = seller_nft_ata.mint == mint.key() @ MarketplaceError::InvalidNFT,
+        constraint = seller_nft_ata.owner == seller.key() @ MarketplaceError::InvalidOwner,
+        constraint = seller_nft_ata.amount == 1 @ MarketplaceError::InvalidNFT,
+    )]
+    pub seller_nft_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"escrow_signer", mint.key().as_ref()],
+        bump
+    )]
+    /// CHECK: PDA delegate authorized for NFT transfer
+    pub escrow_signer: UncheckedAccount<'info>,
+
+    /// Vault receiving the NFT, required for custody-mode listings
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = escrow_signer
+    )]
+    pub escrow_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"allowed_collection", allowed_collection.collection_mint.as_ref()],
+        bump = allowed_collection.bump
+    )]
+    pub allowed_collection: Account<'info, AllowedCollection>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (SOL & ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    /// Listing fee mint (So111... for native SOL)
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = seller)]
+    pub seller_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// Token Metadata accounts; metadata is also read for the collection check
+    pub pnft: PnftAccounts<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelListing<'info> {
+    #[account(
+        mut,
+        seeds = [b"listing", mint.key().as_ref()],
+        bump = listing.bump,
+        has_one = seller,
+        constraint = listing.nft_mint == mint.key() @ MarketplaceError::InvalidNFT,
+        close = seller
+    )]
+    pub listing: Account<'info, Listing>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = seller
+    )]
+    pub seller_nft_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"escrow_signer", mint.key().as_ref()],
+        bump
+    )]
+    /// CHECK: PDA delegate / vault owner for the listed NFT
+    pub escrow_signer: UncheckedAccount<'info>,
+
+    /// Vault holding the NFT, required for custody-mode listings
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = escrow_signer
+    )]
+    pub escrow_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token Metadata accounts, required when the listed mint is a pNFT
+    pub pnft: PnftAccounts<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpgradeListing<'info> {
+    #[account(
+        mut,
+        seeds = [b"listing", mint.key().as_ref()],
+        bump
+    )]
+    /// CHECK: Listing created before `mode` or `payment_mint` existed; resized in logic
+    pub listing: UncheckedAccount<'info>,
+
+    /// CHECK: NFT mint, only used for the listing seeds
+    pub mint: UncheckedAccount<'info>,
+
+    /// Price mint of an SPL listing, supplied by its seller
+    pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(bundle_id: u64)]
+pub struct ListBundle<'info> {
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + 32 + 8 + 4 + (32 * MAX_BUNDLE_SIZE) + 8 + 1 + 1 + 32,
+        seeds = [b"bundle", seller.key().as_ref(), &bundle_id.to_le_bytes()],
+        bump
+    )]
+    pub bundle: Account<'info, BundleListing>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (SOL & ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    /// Payment mint of the bundle, also used for the listing fee (So111... for native SOL)
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = seller)]
+    pub seller_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyBundle<'info> {
+    #[account(
+        mut,
+        seeds = [b"bundle", seller.key().as_ref(), &bundle.bundle_id.to_le_bytes()],
+        bump = bundle.bump,
+        has_one = seller,
+        has_one = payment_mint @ MarketplaceError::InvalidMint,
+        close = seller
+    )]
+    pub bundle: Account<'info, BundleListing>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: Seller wallet, bound to bundle.seller
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+
+    /// Payment mint recorded on the bundle (So111... for native SOL)
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = buyer)]
+    pub buyer_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = seller)]
+    pub seller_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (SOL & ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// Optional fee discount for the buyer
+    pub fee_tier: Option<Account<'info, FeeTier>>,
+
+    /// Token account proving a holding-based fee tier
+    pub fee_tier_holding: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// UOG stake position proving a staking-based fee tier
+    pub fee_tier_stake: Option<Account<'info, UogStake>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBundle<'info> {
+    #[account(
+        mut,
+        seeds = [b"bundle", seller.key().as_ref(), &bundle.bundle_id.to_le_bytes()],
+        bump = bundle.bump,
+        has_one = seller,
+        close = seller
+    )]
+    pub bundle: Account<'info, BundleListing>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(offer_id: u64)]
+pub struct CreateTradeOffer<'info> {
+    #[account(
+        init,
+        payer = maker,
+        space = 8 + 32 + 8 + (1 + 32) + (4 + 32 * MAX_TRADE_OFFER_MINTS) * 2 + 8 + (1 + 32) + 8 + 1,
+        seeds = [b"trade_offer", maker.key().as_ref(), &offer_id.to_le_bytes()],
+        bump
+    )]
+    pub offer: Account<'info, TradeOffer>,
+
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    /// Sweetener mint (UOG), required when token_amount > 0
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, token::mint = token_mint, token::authority = maker)]
+    pub maker_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = token_mint, token::authority = offer)]
+    pub offer_token_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptTradeOffer<'info> {
+    #[account(
+        mut,
+        seeds = [b"trade_offer", maker.key().as_ref(), &offer.offer_id.to_le_bytes()],
+        bump = offer.bump,
+        has_one = maker,
+        close = maker
+    )]
+    pub offer: Account<'info, TradeOffer>,
+
+    #[account(mut)]
+    pub taker: Signer<'info>,
+
+    /// CHECK: Offer maker, bound to offer.maker
+    #[account(mut)]
+    pub maker: AccountInfo<'info>,
+
+    #[account(constraint = Some(token_mint.key()) == offer.token_mint @ MarketplaceError::InvalidTradeOffer)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, token::mint = token_mint, token::authority = offer)]
+    pub offer_token_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = token_mint, token::authority = taker)]
+    pub taker_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (SOL & ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelTradeOffer<'info> {
+    #[account(
+        mut,
+        seeds = [b"trade_offer", maker.key().as_ref(), &offer.offer_id.to_le_bytes()],
+        bump = offer.bump,
+        has_one = maker,
+        close = maker
+    )]
+    pub offer: Account<'info, TradeOffer>,
+
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    #[account(constraint = Some(token_mint.key()) == offer.token_mint @ MarketplaceError::InvalidTradeOffer)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, token::mint = token_mint, token::authority = offer)]
+    pub offer_token_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = token_mint, token::authority = maker)]
+    pub maker_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ListForRent<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + 32 + 32 + 8 + 2 + 1 + (1 + 32) + 8 + 1 + 32,
+        seeds = [b"rental", mint.key().as_ref()],
+        bump
+    )]
+    pub rental: Account<'info, RentalListing>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Mint renters pay in (So111... for native SOL)
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner
+    )]
+    pub owner_nft_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = rental
+    )]
+    pub rental_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RentHero<'info> {
+    #[account(
+        mut,
+        seeds = [b"rental", rental.nft_mint.as_ref()],
+        bump = rental.bump,
+        has_one = owner,
+        has_one = payment_mint @ MarketplaceError::InvalidMint
+    )]
+    pub rental: Account<'info, RentalListing>,
+
+    #[account(mut)]
+    pub renter: Signer<'info>,
+
+    /// CHECK: Hero owner, bound to rental.owner
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
+
+    /// Payment mint recorded on the rental (So111... for native SOL)
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = renter)]
+    pub renter_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = owner)]
+    pub owner_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (SOL & ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// Optional fee discount for the renter
+    pub fee_tier: Option<Account<'info, FeeTier>>,
+
+    /// Token account proving a holding-based fee tier
+    pub fee_tier_holding: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// UOG stake position proving a staking-based fee tier
+    pub fee_tier_stake: Option<Account<'info, UogStake>>,
+
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimRental<'info> {
+    #[account(
+        mut,
+        seeds = [b"rental", mint.key().as_ref()],
+        bump = rental.bump,
+        has_one = owner,
+        constraint = rental.nft_mint == mint.key() @ MarketplaceError::InvalidNFT,
+        close = owner
+    )]
+    pub rental: Account<'info, RentalListing>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner
+    )]
+    pub owner_nft_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = rental
+    )]
+    pub rental_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(nft_mint: Pubkey)]
+pub struct SetHeroRarity<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 1 + 1,
+        seeds = [b"hero_rarity", nft_mint.as_ref()],
+        bump
+    )]
+    pub hero_rarity: Account<'info, HeroRarity>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateHeroStakingPool<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 32 + 32 + 8 + (2 * 4) + 8 + 16 + 8 + 1,
+        seeds = [b"hero_staking_pool", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, HeroStakingPool>,
+
+    /// Verified hero collection NFT
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    /// Emission mint (UOG)
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        token::mint = reward_mint,
+        token::authority = pool
+    )]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StakeHero<'info> {
+    #[account(
+        mut,
+        seeds = [b"hero_staking_pool", pool.collection_mint.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, HeroStakingPool>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + 32 + 32 + 32 + 8 + 16 + 8 + 8 + 1,
+        seeds = [b"hero_stake", mint.key().as_ref()],
+        bump
+    )]
+    pub stake: Account<'info, HeroStake>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// CHECK: Metaplex metadata of the hero, verified in verified_collection
+    pub metadata: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [b"hero_rarity", mint.key().as_ref()],
+        bump
+    )]
+    /// CHECK: Rarity registry PDA of the hero; read in logic because it may not exist yet
+    pub hero_rarity: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner
+    )]
+    pub owner_nft_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = pool
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimHeroRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"hero_staking_pool", pool.collection_mint.as_ref()],
+        bump = pool.bump,
+        has_one = reward_mint,
+        has_one = reward_vault
+    )]
+    pub pool: Account<'info, HeroStakingPool>,
+
+    #[account(
+        mut,
+        seeds = [b"hero_stake", stake.nft_mint.as_ref()],
+        bump = stake.bump,
+        has_one = pool,
+        has_one = owner
+    )]
+    pub stake: Account<'info, HeroStake>,
+
+    pub owner: Signer<'info>,
+
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = owner
+    )]
+    pub owner_reward_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token program owning reward_mint
+    pub reward_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeHero<'info> {
+    #[account(
+        mut,
+        seeds = [b"hero_staking_pool", pool.collection_mint.as_ref()],
+        bump = pool.bump,
+        has_one = reward_mint,
+        has_one = reward_vault
+    )]
+    pub pool: Account<'info, HeroStakingPool>,
+
+    #[account(
+        mut,
+        seeds = [b"hero_stake", mint.key().as_ref()],
+        bump = stake.bump,
+        has_one = pool,
+        has_one = owner,
+        constraint = stake.nft_mint == mint.key() @ MarketplaceError::InvalidNFT,
+        close = owner
+    )]
+    pub stake: Account<'info, HeroStake>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner
+    )]
+    pub owner_nft_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = pool
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = owner
+    )]
+    pub owner_reward_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token program owning the hero mint
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Token program owning reward_mint
+    pub reward_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CreateUogStakingPool<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 32 + 32 + 8 + (2 * 3) + 2 + 8 + 8 + 16 + 8 + 1 + 1 + 1,
+        seeds = [b"uog_staking_pool"],
+        bump
+    )]
+    pub pool: Account<'info, UogStakingPool>,
+
+    pub uog_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"uog_stake_vault"],
+        bump,
+        token::mint = uog_mint,
+        token::authority = pool,
+        token::token_program = token_program
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"uog_reward_vault"],
+        bump,
+        token::mint = uog_mint,
+        token::authority = pool,
+        token::token_program = token_program
+    )]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(tier: u8)]
+pub struct StakeUog<'info> {
+    #[account(
+        mut,
+        seeds = [b"uog_staking_pool"],
+        bump = pool.bump,
+        has_one = uog_mint,
+        has_one = stake_vault
+    )]
+    pub pool: Account<'info, UogStakingPool>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + 32 + 32 + 1 + 8 + 8 + 16 + 8 + 8 + 1,
+        seeds = [b"uog_stake", owner.key().as_ref(), &[tier]],
+        bump
+    )]
+    pub stake: Account<'info, UogStake>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub uog_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = uog_mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimUogRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"uog_staking_pool"],
+        bump = pool.bump,
+        has_one = uog_mint,
+        has_one = reward_vault
+    )]
+    pub pool: Account<'info, UogStakingPool>,
+
+    #[account(
+        mut,
+        seeds = [b"uog_stake", owner.key().as_ref(), &[stake.tier]],
+        bump = stake.bump,
+        has_one = owner
+    )]
+    pub stake: Account<'info, UogStake>,
+
+    pub owner: Signer<'info>,
+
+    pub uog_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = uog_mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeUog<'info> {
+    #[account(
+        mut,
+        seeds = [b"uog_staking_pool"],
+        bump = pool.bump,
+        has_one = uog_mint,
+        has_one = stake_vault,
+        has_one = reward_vault
+    )]
+    pub pool: Account<'info, UogStakingPool>,
+
+    #[account(
+        mut,
+        seeds = [b"uog_stake", owner.key().as_ref(), &[stake.tier]],
+        bump = stake.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub stake: Account<'info, UogStake>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub uog_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = uog_mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyWithdrawUog<'info> {
+    #[account(
+        mut,
+        seeds = [b"uog_staking_pool"],
+        bump = pool.bump,
+        has_one = uog_mint,
+        has_one = stake_vault
+    )]
+    pub pool: Account<'info, UogStakingPool>,
+
+    #[account(
+        mut,
+        seeds = [b"uog_stake", owner.key().as_ref(), &[stake.tier]],
+        bump = stake.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub stake: Account<'info, UogStake>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub uog_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = uog_mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: PDA treasury
+    #[account(
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    pub treasury_pda: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        token::mint = uog_mint,
+        token::authority = treasury_pda
+    )]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(pack_id: u64)]
+pub struct CreateGatchaPack<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 8 + 8 + 1 + 32 + (2 * 4) + 8 + 8 + 8
+            + (4 + MAX_PACK_NAME_LEN) + (4 + MAX_PACK_SYMBOL_LEN) + (4 + MAX_PACK_URI_LEN) + (2 * 3) + 1,
+        seeds = [b"gatcha_pack", pack_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pack: Account<'info, GatchaPack>,
+
+    /// Pack price mint (So111... for native SOL)
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyPack<'info> {
+    #[account(
+        mut,
+        seeds = [b"gatcha_pack", pack.pack_id.to_le_bytes().as_ref()],
+        bump = pack.bump,
+        has_one = payment_mint
+    )]
+    pub pack: Account<'info, GatchaPack>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + 32 + 32 + 8 + 32 + 8 + 8 + 1,
+        seeds = [b"pack_purchase", pack.key().as_ref(), pack.sold.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub purchase: Account<'info, PackPurchase>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (SOL & ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = buyer)]
+    pub buyer_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
+
+    /// Payer's referral registry, when they were referred
+    #[account(seeds = [b"referral", buyer.key().as_ref()], bump = referral.bump)]
+    pub referral: Option<Account<'info, Referral>>,
+
+    /// Referrer's registry, credited with the referral stats
+    #[account(
+        mut,
+        seeds = [b"referral", referrer_stats.player.as_ref()],
+        bump = referrer_stats.bump
+    )]
+    pub referrer_stats: Option<Account<'info, Referral>>,
+
+    /// CHECK: Referrer wallet (SOL) or token account (SPL), checked in pay_referral_share
+    #[account(mut)]
+    pub referrer_destination: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OpenPack<'info> {
+    #[account(
+        mut,
+        seeds = [b"gatcha_pack", pack.pack_id.to_le_bytes().as_ref()],
+        bump = pack.bump
+    )]
+    pub pack: Account<'info, GatchaPack>,
+
+    #[account(
+        mut,
+        seeds = [b"pack_purchase", pack.key().as_ref(), purchase.index.to_le_bytes().as_ref()],
+        bump = purchase.bump,
+        has_one = pack,
+        has_one = buyer,
+        close = buyer
+    )]
+    pub purchase: Account<'info, PackPurchase>,
+
+    /// CHECK: Pack buyer and hero recipient, bound to purchase.buyer
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
+
+    /// The buyer while the reveal window is open, anyone after it
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = mint.supply == 0 && mint.decimals == 0 @ MarketplaceError::InvalidNFT,
+        mint::authority = mint_authority
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = buyer
+    )]
+    pub buyer_nft_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"mint_auth", mint.key().as_ref()],
+        bump
+    )]
+    /// CHECK: PDA authority for mint
+    pub mint_authority: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 32 + 1 + 1,
+        seeds = [b"hero_rarity", mint.key().as_ref()],
+        bump
+    )]
+    pub hero_rarity: Account<'info, HeroRarity>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + 32 + 32 + 8 + 2 + 2 + 1,
+        seeds = [b"pity", pack.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub pity_counter: Account<'info, PityCounter>,
+
+    /// CHECK: Metadata PDA of mint, verified against the mint for legacy NFTs
+    #[account(mut)]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: Metaplex Metadata Program
+    #[account(address = mpl_token_metadata::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    /// CHECK: Update authority for metadata, must be the market admin
+    #[account(address = market_config.admin @ MarketplaceError::Unauthorized)]
+    pub update_authority: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// CHECK: SlotHashes sysvar, parsed in slot_hash_after
+    #[account(address = sysvar::slot_hashes::ID)]
+    pub slot_hashes: UncheckedAccount<'info>,
+
+    /// Token program owning the NFT mint
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Hero collection the revealed mint is added to and verified in
+    pub hero_collection: HeroCollectionAccounts<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: u64)]
+pub struct CreateTournament<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 8 + 32 + 1 + 32 + 8 + 2 + 2 + 2 + 8 + 8 + 8
+            + (4 + 2 * MAX_PAYOUT_PLACES) + 2 + 32 + 1 + 1 + 8,
+        seeds = [b"tournament", tournament_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + 8 + 1,
+        seeds = [b"tournament_counter"],
+        bump
+    )]
+    pub tournament_counter: Account<'info, TournamentCounter>,
+
+    /// Entry fee mint (So111... for native SOL)
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    /// Prize vault for SPL tournaments, owned by the tournament PDA
+    #[account(
+        token::mint = payment_mint,
+        token::authority = tournament
+    )]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SponsorTournament<'info> {
+    #[account(
+        mut,
+        seeds = [b"tournament", tournament.tournament_id.to_le_bytes().as_ref()],
+        bump = tournament.bump,
+        has_one = payment_mint
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    #[account(
+        init_if_needed,
+        payer = sponsor,
+        space = 8 + 32 + 32 + 8 + 1,
+        seeds = [b"tournament_sponsor", tournament.key().as_ref(), sponsor.key().as_ref()],
+        bump
+    )]
+    pub sponsorship: Account<'info, TournamentSponsor>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
 
-            let treasury_pda_acc = ctx
-                .remaining_accounts
-                .iter()
-                .find(|a| a.key() == ctx.accounts.treasury_pda.key())
-                .ok_or(MarketplaceError::InvalidOwner)?;
+    pub payment_mint: InterfaceAccount<'info, Mint>,
 
-            let treasury_token_acc = ctx
-                .remaining_accounts
-                .iter()
-                .find(|a| a.key() == ctx.accounts.treasury_token_account.key())
-                .ok_or(MarketplaceError::InvalidOwner)?;
+    #[account(mut, token::mint = payment_mint, token::authority = sponsor)]
+    pub sponsor_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
 
-            if output_mint == native_mint::id() {
-                let sys_acc = ctx
-                    .remaining_accounts
-                    .iter()
-                    .find(|a| a.key() == anchor_lang::system_program::ID)
-                    .ok_or(MarketplaceError::InvalidOwner)?;
+    #[account(mut, address = tournament.vault @ MarketplaceError::MissingEscrowVault)]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
 
-                invoke(
-                    &system_instruction::transfer(
-                        &ctx.accounts.user.key(),
-                        &ctx.accounts.treasury_pda.key(),
-                        trade_fee,
-                    ),
-                    &[user_acc.clone(), treasury_pda_acc.clone(), sys_acc.clone()],
-                )?;
-            } else {
-                let token_acc = ctx
-                    .remaining_accounts
-                    .iter()
-                    .find(|a| a.key() == spl_token::ID)
-                    .ok_or(MarketplaceError::InvalidOwner)?;
-
-                let ix = token_instruction::transfer(
-                    &spl_token::ID,
-                    &ctx.accounts.user_out_token_account.key(),
-                    &ctx.accounts.treasury_token_account.key(),
-                    &ctx.accounts.user.key(),
-                    &[],
-                    trade_fee,
-                )?;
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
 
-                let user_out_acc = ctx
-                    .remaining_accounts
-                    .iter()
-                    .find(|a| a.key() == ctx.accounts.user_out_token_account.key())
-                    .ok_or(MarketplaceError::InvalidOwner)?;
+    pub system_program: Program<'info, System>,
+}
 
-                invoke(
-                    &ix,
-                    &[
-                        user_out_acc.clone(),
-                        treasury_token_acc.clone(),
-                        user_acc.clone(),
-                        token_acc.clone(),
-                    ],
-                )?;
-            }
-        }
+#[derive(Accounts)]
+pub struct JoinTournament<'info> {
+    #[account(
+        mut,
+        seeds = [b"tournament", tournament.tournament_id.to_le_bytes().as_ref()],
+        bump = tournament.bump,
+        has_one = payment_mint
+    )]
+    pub tournament: Account<'info, Tournament>,
 
-        Ok(())
-    }
+    #[account(
+        init,
+        payer = player,
+        space = 8 + 32 + 32 + 8 + 1,
+        seeds = [b"tournament_entry", tournament.key().as_ref(), player.key().as_ref()],
+        bump
+    )]
+    pub participant: Account<'info, TournamentParticipant>,
 
-    pub fn relist_nft(ctx: Context<RelistNft>, new_price: u64, use_sol: bool, relist_fee_spl: u64) -> Result<()> {
-        let listing = &mut ctx.accounts.listing;
+    #[account(mut)]
+    pub player: Signer<'info>,
 
-        require_keys_eq!(
-            ctx.accounts.new_owner.key(),
-            ctx.accounts.seller_nft_ata.owner,
-            MarketplaceError::InvalidOwner
-        );
-        require_keys_eq!(
-            listing.nft_mint,
-            ctx.accounts.mint.key(),
-            MarketplaceError::InvalidNFT
-        );
+    pub payment_mint: InterfaceAccount<'info, Mint>,
 
-        listing.seller = ctx.accounts.new_owner.key();
-        listing.price = new_price;
-        listing.use_sol = use_sol;
+    #[account(mut, token::mint = payment_mint, token::authority = player)]
+    pub player_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
 
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.seller_payment_ata.to_account_info(),
-                    to: ctx.accounts.treasury_token_account.to_account_info(),
-                    authority: ctx.accounts.new_owner.to_account_info(),
-                },
-            ),
-            relist_fee_spl,
-        )?;
+    #[account(mut, address = tournament.vault @ MarketplaceError::MissingEscrowVault)]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
 
-        token::approve(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                token::Approve {
-                    to: ctx.accounts.seller_nft_ata.to_account_info(),
-                    delegate: ctx.accounts.escrow_signer.to_account_info(),
-                    authority: ctx.accounts.new_owner.to_account_info(),
-                },
-            ),
-            1,
-        )?;
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
 
-        Ok(())
-    }
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct SwapToken<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+pub struct SettleTournament<'info> {
+    #[account(
+        mut,
+        seeds = [b"tournament", tournament.tournament_id.to_le_bytes().as_ref()],
+        bump = tournament.bump,
+        has_one = payment_mint,
+        close = treasury_pda
+    )]
+    pub tournament: Account<'info, Tournament>,
 
-    /// CHECK: DEX aggregator program (e.g. DFLOW)
-    pub dex_program: UncheckedAccount<'info>,
+    /// Anyone may submit the signed standings
+    #[account(mut)]
+    pub settler: Signer<'info>,
 
+    /// Writable so fees withheld on the vault can be harvested before it closes
     #[account(mut)]
-    pub market_config: Account<'info, MarketConfig>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, address = tournament.vault @ MarketplaceError::MissingEscrowVault)]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
@@ -558,261 +8785,525 @@ pub struct SwapToken<'info> {
     /// CHECK: Treasury PDA (SOL & ATA authority)
     pub treasury_pda: AccountInfo<'info>,
 
-    /// CHECK: No validation
-    pub output_mint: UncheckedAccount<'info>,
+    #[account(mut, token::mint = payment_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// CHECK: Instructions sysvar, used to find the Ed25519 verification
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CloseTournamentEntry<'info> {
+    /// CHECK: Closed tournament account, only its address is used
+    pub tournament: UncheckedAccount<'info>,
 
     #[account(
         mut,
-        associated_token::mint = output_mint,
-        associated_token::authority = treasury_pda
+        seeds = [b"tournament_entry", tournament.key().as_ref(), player.key().as_ref()],
+        bump = participant.bump,
+        has_one = tournament,
+        has_one = player,
+        close = player
     )]
-    pub treasury_token_account: Account<'info, TokenAccount>,
+    pub participant: Account<'info, TournamentParticipant>,
 
+    #[account(mut)]
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelTournament<'info> {
     #[account(
         mut,
-        associated_token::mint = output_mint,
-        associated_token::authority = user
+        seeds = [b"tournament", tournament.tournament_id.to_le_bytes().as_ref()],
+        bump = tournament.bump
     )]
-    pub user_out_token_account: Account<'info, TokenAccount>,
+    pub tournament: Account<'info, Tournament>,
+
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
 }
 
-#[account]
-pub struct Listing {
-    pub seller: Pubkey,
-    pub nft_mint: Pubkey,
-    pub price: u64,
-    pub use_sol: bool,
-    pub bump: u8,
+#[derive(Accounts)]
+pub struct ClaimRefund<'info> {
+    #[account(
+        mut,
+        seeds = [b"tournament", tournament.tournament_id.to_le_bytes().as_ref()],
+        bump = tournament.bump,
+        has_one = payment_mint
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    /// Entry receipt, closed on refund
+    #[account(
+        mut,
+        seeds = [b"tournament_entry", tournament.key().as_ref(), player.key().as_ref()],
+        bump = participant.bump,
+        has_one = tournament,
+        has_one = player,
+        close = player
+    )]
+    pub participant: Account<'info, TournamentParticipant>,
+
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = player)]
+    pub player_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = tournament.vault @ MarketplaceError::MissingEscrowVault)]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
 }
 
-#[account]
-pub struct MarketConfig {
-    pub admin: Pubkey,
-    pub mint_fee_bps: u16,
-    pub trade_fee_bps: u16,
-    pub relist_fee_bps: u16,
-    pub treasury_bump: u8,
-    pub multisig_admins: Vec<Pubkey>,
-    pub multisig_threshold: u8,
+#[derive(Accounts)]
+pub struct ClaimSponsorRefund<'info> {
+    #[account(
+        mut,
+        seeds = [b"tournament", tournament.tournament_id.to_le_bytes().as_ref()],
+        bump = tournament.bump,
+        has_one = payment_mint
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    /// Sponsor receipt, closed on refund
+    #[account(
+        mut,
+        seeds = [b"tournament_sponsor", tournament.key().as_ref(), sponsor.key().as_ref()],
+        bump = sponsorship.bump,
+        has_one = tournament,
+        has_one = sponsor,
+        close = sponsor
+    )]
+    pub sponsorship: Account<'info, TournamentSponsor>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = sponsor)]
+    pub sponsor_payment_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = tournament.vault @ MarketplaceError::MissingEscrowVault)]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
 }
 
-#[error_code]
-pub enum MarketplaceError {
-    #[msg("Invalid Owner")]
-    InvalidOwner,
-    #[msg("Invalid NFT")]
-    InvalidNFT,    
-    #[msg("Math Over flow")]
-    MathOverflow,
-    #[msg("InvalidThreshold")]
-    InvalidThreshold,
-    #[msg("Unauthorized action")]
-    Unauthorized,
+#[derive(Accounts)]
+pub struct CloseSponsorReceipt<'info> {
+    /// CHECK: Closed tournament account, only its address is used
+    pub tournament: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"tournament_sponsor", tournament.key().as_ref(), sponsor.key().as_ref()],
+        bump = sponsorship.bump,
+        has_one = tournament,
+        has_one = sponsor,
+        close = sponsor
+    )]
+    pub sponsorship: Account<'info, TournamentSponsor>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeMarket<'info> {
+pub struct CloseCancelledTournament<'info> {
     #[account(
-        init,
+        mut,
+        seeds = [b"tournament", tournament.tournament_id.to_le_bytes().as_ref()],
+        bump = tournament.bump,
+        has_one = payment_mint,
+        close = treasury_pda
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    /// Writable so fees withheld on the vault can be harvested before it closes
+    #[account(mut)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, address = tournament.vault @ MarketplaceError::MissingEscrowVault)]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (SOL & ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    #[account(mut, token::mint = payment_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    /// Token program owning payment_mint
+    pub payment_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SetBattleConfig<'info> {
+    #[account(
+        init_if_needed,
         payer = admin,
-        space = 8 + 32 + 2 + 2 + 2 + 1 + 4 + (32 * 5) + 1,
-        seeds = [b"market_config"],
+        space = 8 + 32 + 2 + 8 + 1 + 32,
+        seeds = [b"battle_config"],
         bump
     )]
-    pub market_config: Account<'info, MarketConfig>,
+    pub battle_config: Account<'info, BattleConfig>,
 
-    #[account(seeds = [b"treasury"], bump)]
-    /// CHECK: only used as a PDA to store SOL/SPL
-    pub treasury_pda: AccountInfo<'info>,
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
 
     #[account(mut)]
     pub admin: Signer<'info>,
+
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeTreasury<'info> {
+#[instruction(match_id: u64)]
+pub struct CreateWager<'info> {
     #[account(
         init,
-        payer = admin,
-        space = 8,
-        seeds = [b"treasury"],
+        payer = player_a,
+        space = 8 + 8 + 32 + 32 + 32 + 32 + 8 + 2 * 32 * battle::TEAM_SIZE + 2 * battle::TEAM_SIZE
+            + 1 + 8 + 1 + 1 + 8 + 32 + 32 + 8,
+        seeds = [b"wager", player_a.key().as_ref(), match_id.to_le_bytes().as_ref()],
         bump
     )]
-    /// CHECK: only stores lamports
-    pub treasury_pda: AccountInfo<'info>,
+    pub wager: Account<'info, WagerMatch>,
+
+    #[account(
+        init,
+        payer = player_a,
+        seeds = [b"wager_vault", wager.key().as_ref()],
+        bump,
+        token::mint = uog_mint,
+        token::authority = vault,
+        token::token_program = token_program
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
     #[account(mut)]
-    pub admin: Signer<'info>,
+    pub player_a: Signer<'info>,
+
+    #[account(address = battle_config.uog_mint @ MarketplaceError::InvalidMint)]
+    pub uog_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = uog_mint,
+        token::authority = player_a
+    )]
+    pub player_a_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"battle_config"], bump = battle_config.bump)]
+    pub battle_config: Account<'info, BattleConfig>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptWager<'info> {
+    #[account(
+        mut,
+        seeds = [b"wager", wager.player_a.as_ref(), wager.match_id.to_le_bytes().as_ref()],
+        bump = wager.bump,
+        has_one = player_b,
+        has_one = uog_mint,
+        has_one = vault
+    )]
+    pub wager: Account<'info, WagerMatch>,
+
+    #[account(mut)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub player_b: Signer<'info>,
+
+    pub uog_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = uog_mint,
+        token::authority = player_b
+    )]
+    pub player_b_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelWager<'info> {
+    #[account(
+        mut,
+        seeds = [b"wager", player_a.key().as_ref(), wager.match_id.to_le_bytes().as_ref()],
+        bump = wager.bump,
+        has_one = player_a,
+        has_one = uog_mint,
+        has_one = vault,
+        close = player_a
+    )]
+    pub wager: Account<'info, WagerMatch>,
+
+    #[account(mut)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub player_a: Signer<'info>,
+
+    pub uog_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = uog_mint,
+        token::authority = player_a
+    )]
+    pub player_a_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct MintAndList<'info> {
+pub struct SettleWager<'info> {
     #[account(
-        init,
-        payer = seller,
-        space = 8 + 32 + 32 + 8 + 1 + 1,
-        seeds = [b"listing", mint.key().as_ref()],
-        bump
+        mut,
+        seeds = [b"wager", wager.player_a.as_ref(), wager.match_id.to_le_bytes().as_ref()],
+        bump = wager.bump
     )]
-    pub listing: Account<'info, Listing>,
+    pub wager: Account<'info, WagerMatch>,
+
+    #[account(seeds = [b"battle_config"], bump = battle_config.bump)]
+    pub battle_config: Account<'info, BattleConfig>,
 
+    /// CHECK: Instructions sysvar, used to find the Ed25519 verification
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawExpiredWager<'info> {
     #[account(
-        seeds = [b"escrow_signer", mint.key().as_ref()],
-        bump
+        mut,
+        seeds = [b"wager", player_a.key().as_ref(), wager.match_id.to_le_bytes().as_ref()],
+        bump = wager.bump,
+        has_one = player_a,
+        has_one = uog_mint,
+        has_one = vault,
+        close = player_a
     )]
-    /// CHECK: PDA escrow signer for ATA approval
-    pub escrow_signer: UncheckedAccount<'info>,
-
-    /// Seller: signer
-    #[account(mut)]
-    pub seller: Signer<'info>,
+    pub wager: Account<'info, WagerMatch>,
 
     #[account(mut)]
-    pub mint: Account<'info, Mint>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// CHECK: Seller NFT ATA
+    /// CHECK: Wager creator, receives the escrow rent
     #[account(mut)]
-    pub seller_nft_ata: UncheckedAccount<'info>,
+    pub player_a: UncheckedAccount<'info>,
 
     #[account(
-        seeds = [b"mint_auth", mint.key().as_ref()],
-        bump
+        mut,
+        token::mint = uog_mint,
+        token::authority = player_a
     )]
-    /// CHECK: PDA authority for mint
-    pub mint_authority: UncheckedAccount<'info>,
+    pub player_a_token_account: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
-        seeds = [b"treasury"],
-        bump = market_config.treasury_bump
+        token::mint = uog_mint,
+        token::authority = wager.player_b
     )]
-    /// CHECK: PDA treasury to store lamports or SPL
-    pub treasury_pda: UncheckedAccount<'info>,
+    pub player_b_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    /// CHECK: Mint of payment SOL or SPL
-    pub payment_mint: UncheckedAccount<'info>,
+    pub uog_mint: InterfaceAccount<'info, Mint>,
 
-    /// CHECK: Mint SPL for fee
-    pub spl_mint: UncheckedAccount<'info>,
+    #[account(seeds = [b"battle_config"], bump = battle_config.bump)]
+    pub battle_config: Account<'info, BattleConfig>,
 
-    /// CHECK: Treasury ATA
-    #[account(mut)]
-    pub treasury_token_account: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeWager<'info> {
+    #[account(
+        mut,
+        seeds = [b"wager", player_a.key().as_ref(), wager.match_id.to_le_bytes().as_ref()],
+        bump = wager.bump,
+        has_one = player_a,
+        has_one = uog_mint,
+        has_one = vault,
+        close = player_a
+    )]
+    pub wager: Account<'info, WagerMatch>,
 
-    /// CHECK: Seller payment ATA
     #[account(mut)]
-    pub seller_payment_ata: UncheckedAccount<'info>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
+    /// CHECK: Wager creator, receives the escrow rent
     #[account(mut)]
-    pub market_config: Account<'info, MarketConfig>,
+    pub player_a: UncheckedAccount<'info>,
 
-    /// CHECK: Admin for SOL fee
-    #[account(mut, address = market_config.admin)]
-    pub admin: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        token::mint = uog_mint,
+        token::authority = player_a
+    )]
+    pub player_a_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    /// CHECK: Admin for SPL fee
-    #[account(mut)]
-    pub admin_token_account: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        token::mint = uog_mint,
+        token::authority = wager.player_b
+    )]
+    pub player_b_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    /// CHECK: Metadata PDA of mint
-    #[account(mut)]
-    pub metadata: UncheckedAccount<'info>,
+    pub uog_mint: InterfaceAccount<'info, Mint>,
 
-    /// CHECK: Metaplex Metadata Program
-    pub token_metadata_program: UncheckedAccount<'info>,
+    #[account(seeds = [b"battle_config"], bump = battle_config.bump)]
+    pub battle_config: Account<'info, BattleConfig>,
 
-    /// CHECK: Update authority for metadata
-    pub update_authority: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
 
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+    #[account(mut, token::mint = uog_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    /// CHECK: Rent sysvar
-    pub rent: UncheckedAccount<'info>,
+    #[account(seeds = [b"market_config"], bump)]
+    pub market_config: Account<'info, MarketConfig>,
+
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct BuyNft<'info> {
-    #[account(mut)]
-    pub listing: Account<'info, Listing>,
-
-    #[account(mut)]
-    pub buyer: Signer<'info>,
+pub struct DisputeWager<'info> {
+    #[account(
+        mut,
+        seeds = [b"wager", player_a.key().as_ref(), wager.match_id.to_le_bytes().as_ref()],
+        bump = wager.bump,
+        has_one = player_a,
+        has_one = uog_mint,
+        has_one = vault,
+        close = player_a
+    )]
+    pub wager: Account<'info, WagerMatch>,
 
-    /// CHECK: This is the seller wallet account.
     #[account(mut)]
-    pub seller: AccountInfo<'info>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// CHECK: This is the buyer ata or native wallet.
-    #[account(mut)]
-    pub buyer_payment_ata: AccountInfo<'info>,
+    /// Either player
+    pub disputer: Signer<'info>,
 
-    /// CHECK: This is the seller ata.
+    /// CHECK: Wager creator, receives the escrow rent
     #[account(mut)]
-    pub seller_payment_ata: AccountInfo<'info>,
+    pub player_a: UncheckedAccount<'info>,
 
-    /// CHECK: This is the treasury ata.
-    #[account(mut)]
-    pub treasury_token_account: AccountInfo<'info>,
+    #[account(
+        mut,
+        token::mint = uog_mint,
+        token::authority = player_a
+    )]
+    pub player_a_token_account: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
+        token::mint = uog_mint,
+        token::authority = wager.player_b
+    )]
+    pub player_b_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub uog_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(seeds = [b"battle_config"], bump = battle_config.bump)]
+    pub battle_config: Account<'info, BattleConfig>,
+
+    #[account(
         seeds = [b"treasury"],
         bump = market_config.treasury_bump
     )]
-    /// CHECK:
+    /// CHECK: Treasury PDA (ATA authority)
     pub treasury_pda: AccountInfo<'info>,
 
-    #[account(mut)]
-    pub seller_nft_ata: Account<'info, TokenAccount>,
-
-    #[account(mut)]
-    pub buyer_nft_ata: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = uog_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
 
+    #[account(seeds = [b"market_config"], bump)]
     pub market_config: Account<'info, MarketConfig>,
 
-    /// CHECK: add seed bump
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(epoch: u64, merkle_root: [u8; 32], total_amount: u64, num_claims: u32)]
+pub struct CreateDistribution<'info> {
     #[account(
-        mut,
-        seeds = [b"escrow_signer", listing.nft_mint.as_ref()],
+        init,
+        payer = admin,
+        space = 8 + 8 + 32 + 32 + 32 + 8 + 8 + 4 + 8 + (4 + claim_bitmap_len(num_claims)) + 1,
+        seeds = [b"distribution", epoch.to_le_bytes().as_ref()],
         bump
     )]
-    /// CHECK: Escrow signer PDA authorized for NFT transfer
-    pub escrow_signer: AccountInfo<'info>,
+    pub distribution: Account<'info, Distribution>,
 
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-}
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"distribution_vault", distribution.key().as_ref()],
+        bump,
+        token::mint = uog_mint,
+        token::authority = distribution,
+        token::token_program = token_program
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
-#[derive(Accounts)]
-pub struct WithdrawTreasury<'info> {
-    #[account(mut, has_one = admin)]
-    pub market_config: Account<'info, MarketConfig>,
+    pub uog_mint: InterfaceAccount<'info, Mint>,
 
-    /// CHECK: PDA treasury (authority)
     #[account(
         mut,
-        seeds = [b"treasury"],
-        bump = market_config.treasury_bump
+        token::mint = uog_mint,
+        token::authority = admin
     )]
-    pub treasury_pda: AccountInfo<'info>,
-
-    /// SPL mint (UOG, USDC, etc) or So111...
-    /// CHECK: validated
-    pub mint: AccountInfo<'info>,
+    pub admin_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    /// Token account treasury_pda
-    #[account(mut)]
-    pub treasury_token_account: Option<Account<'info, TokenAccount>>,
-
-    /// Token account admin
-    #[account(mut)]
-    pub admin_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(seeds = [b"market_config"], bump, has_one = admin)]
+    pub market_config: Account<'info, MarketConfig>,
 
-    /// Optional: legacy admin mode
     #[account(mut)]
     pub admin: Signer<'info>,
 
@@ -822,82 +9313,521 @@ pub struct WithdrawTreasury<'info> {
     /// Admin 2
     pub signer2: Signer<'info>,
 
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct SendToken<'info> {
-    #[account(mut)]
-    pub sender: Signer<'info>,
+pub struct ClaimEarnings<'info> {
+    #[account(
+        mut,
+        seeds = [b"distribution", distribution.epoch.to_le_bytes().as_ref()],
+        bump = distribution.bump,
+        has_one = uog_mint,
+        has_one = vault
+    )]
+    pub distribution: Account<'info, Distribution>,
 
-    /// CHECK: raw recipient account, validated in instruction logic
     #[account(mut)]
-    pub recipient: AccountInfo<'info>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// CHECK: treasury PDA validated manually in logic
-    #[account(mut)]
-    pub treasury_pda: AccountInfo<'info>,
+    pub claimant: Signer<'info>,
 
-    /// CHECK: token mint account
-    pub mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        token::mint = uog_mint,
+        token::authority = claimant
+    )]
+    pub claimant_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    /// CHECK: ATA of sender
-    #[account(mut)]
-    pub sender_token_account: AccountInfo<'info>,
+    pub uog_mint: InterfaceAccount<'info, Mint>,
 
-    /// CHECK: ATA of recipient
-    #[account(mut)]
-    pub recipient_token_account: AccountInfo<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SweepDistribution<'info> {
+    #[account(
+        mut,
+        seeds = [b"distribution", distribution.epoch.to_le_bytes().as_ref()],
+        bump = distribution.bump,
+        has_one = uog_mint,
+        has_one = vault,
+        close = treasury_pda
+    )]
+    pub distribution: Account<'info, Distribution>,
 
-    /// CHECK: ATA of treasury
     #[account(mut)]
-    pub treasury_token_account: AccountInfo<'info>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// Market config
+    pub uog_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = market_config.treasury_bump
+    )]
+    /// CHECK: Treasury PDA (ATA authority)
+    pub treasury_pda: AccountInfo<'info>,
+
+    #[account(mut, token::mint = uog_mint, token::authority = treasury_pda)]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"market_config"], bump)]
     pub market_config: Account<'info, MarketConfig>,
 
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct RelistNft<'info> {
+pub struct SetReferralShare<'info> {
     #[account(
         mut,
-        seeds = [b"listing", mint.key().as_ref()],
-        bump = listing.bump,
-        constraint = listing.nft_mint == mint.key() @ MarketplaceError::InvalidNFT
+        seeds = [b"market_config"],
+        bump,
+        has_one = admin
     )]
-    pub listing: Account<'info, Listing>,
+    pub market_config: Account<'info, MarketConfig>,
 
-    #[account(mut)]
-    pub new_owner: Signer<'info>,
+    pub admin: Signer<'info>,
 
-    #[account(mut)]
-    pub mint: Account<'info, Mint>,
+    /// Admin 1
+    pub signer1: Signer<'info>,
+
+    /// Admin 2
+    pub signer2: Signer<'info>,
+}
 
+#[derive(Accounts)]
+#[instruction(referrer: Pubkey)]
+pub struct RegisterReferrer<'info> {
     #[account(
-        mut,
-        constraint = seller_nft_ata.mint == mint.key() @ MarketplaceError::InvalidNFT,
-        constraint = seller_nft_ata.owner == new_owner.key() @ MarketplaceError::InvalidOwner,
+        init_if_needed,
+        payer = player,
+        space = 8 + 32 + (1 + 32) + 4 + 8 + 8 + 8 + 1,
+        seeds = [b"referral", player.key().as_ref()],
+        bump
     )]
-    pub seller_nft_ata: Account<'info, TokenAccount>,
-
-    /// CHECK: Seller SPL ATA (UOG)
-    #[account(mut)]
-    pub seller_payment_ata: UncheckedAccount<'info>,
-
-    /// CHECK: Treasury ATA SPL
-    #[account(mut)]
-    pub treasury_token_account: UncheckedAccount<'info>,
+    pub player_referral: Account<'info, Referral>,
 
     #[account(
-        seeds = [b"escrow_signer", mint.key().as_ref()],
+        init_if_needed,
+        payer = player,
+        space = 8 + 32 + (1 + 32) + 4 + 8 + 8 + 8 + 1,
+        seeds = [b"referral", referrer.as_ref()],
         bump
     )]
-    /// CHECK: PDA delegate authorized for NFT transfer
-    pub escrow_signer: UncheckedAccount<'info>,
+    pub referrer_referral: Account<'info, Referral>,
+
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::hash::hash;
+
+    fn event_discriminator(name: &str) -> [u8; 8] {
+        let mut discriminator = [0u8; 8];
+        discriminator.copy_from_slice(&hash(format!("event:{}", name).as_bytes()).to_bytes()[..8]);
+        discriminator
+    }
+
+    /// Splits the bytes `emit!` logs into discriminator and Borsh body.
+    fn logged<E: anchor_lang::Event>(event: &E) -> ([u8; 8], Vec<u8>) {
+        let data = event.data();
+        let mut discriminator = [0u8; 8];
+        discriminator.copy_from_slice(&data[..8]);
+        (discriminator, data[8..].to_vec())
+    }
+
+    #[test]
+    fn market_initialized_log_layout() {
+        let admin = Pubkey::new_unique();
+        let (discriminator, body) = logged(&MarketInitialized {
+            admin,
+            mint_fee_bps: 250,
+            trade_fee_bps: 300,
+            relist_fee_bps: 100,
+            transfer_fee_bps: 50,
+            multisig_threshold: 2,
+        });
+        assert_eq!(discriminator, event_discriminator("MarketInitialized"));
+
+        let decoded = MarketInitialized::try_from_slice(&body).unwrap();
+        assert_eq!(decoded.admin, admin);
+        assert_eq!(decoded.mint_fee_bps, 250);
+        assert_eq!(decoded.trade_fee_bps, 300);
+        assert_eq!(decoded.relist_fee_bps, 100);
+        assert_eq!(decoded.transfer_fee_bps, 50);
+        assert_eq!(decoded.multisig_threshold, 2);
+        assert_eq!(body.len(), 32 + 2 + 2 + 2 + 2 + 1);
+    }
+
+    #[test]
+    fn nft_minted_and_listed_log_layout() {
+        let seller = Pubkey::new_unique();
+        let nft_mint = Pubkey::new_unique();
+        let (discriminator, body) = logged(&NftMintedAndListed {
+            seller,
+            nft_mint,
+            price: 1_000_000,
+            use_sol: true,
+            mint_fee: 25_000,
+        });
+        assert_eq!(discriminator, event_discriminator("NftMintedAndListed"));
+
+        let decoded = NftMintedAndListed::try_from_slice(&body).unwrap();
+        assert_eq!(decoded.seller, seller);
+        assert_eq!(decoded.nft_mint, nft_mint);
+        assert_eq!(decoded.price, 1_000_000);
+        assert!(decoded.use_sol);
+        assert_eq!(decoded.mint_fee, 25_000);
+    }
+
+    #[test]
+    fn nft_sold_log_layout() {
+        let nft_mint = Pubkey::new_unique();
+        let buyer = Pubkey::new_unique();
+        let seller = Pubkey::new_unique();
+        let (discriminator, body) = logged(&NftSold {
+            nft_mint,
+            buyer,
+            seller,
+            price: 5_000_000,
+            fee: 150_000,
+            seller_proceeds: 4_850_000,
+            use_sol: false,
+        });
+        assert_eq!(discriminator, event_discriminator("NftSold"));
+
+        let decoded = NftSold::try_from_slice(&body).unwrap();
+        assert_eq!(decoded.nft_mint, nft_mint);
+        assert_eq!(decoded.buyer, buyer);
+        assert_eq!(decoded.seller, seller);
+        assert_eq!(decoded.price, 5_000_000);
+        assert_eq!(decoded.fee, 150_000);
+        assert_eq!(decoded.seller_proceeds, 4_850_000);
+        assert!(!decoded.use_sol);
+        assert_eq!(body.len(), 32 * 3 + 8 + 8 + 8 + 1);
+    }
+
+    #[test]
+    fn nft_relisted_log_layout() {
+        let nft_mint = Pubkey::new_unique();
+        let seller = Pubkey::new_unique();
+        let (discriminator, body) = logged(&NftRelisted {
+            nft_mint,
+            seller,
+            price: 2_000_000,
+            use_sol: true,
+            relist_fee: 20_000,
+        });
+        assert_eq!(discriminator, event_discriminator("NftRelisted"));
+
+        let decoded = NftRelisted::try_from_slice(&body).unwrap();
+        assert_eq!(decoded.nft_mint, nft_mint);
+        assert_eq!(decoded.seller, seller);
+        assert_eq!(decoded.price, 2_000_000);
+        assert!(decoded.use_sol);
+        assert_eq!(decoded.relist_fee, 20_000);
+    }
+
+    #[test]
+    fn token_sent_log_layout() {
+        let sender = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let (discriminator, body) = logged(&TokenSent {
+            sender,
+            recipient,
+            mint: native_mint::id(),
+            amount: 990,
+            fee: 10,
+        });
+        assert_eq!(discriminator, event_discriminator("TokenSent"));
+
+        let decoded = TokenSent::try_from_slice(&body).unwrap();
+        assert_eq!(decoded.sender, sender);
+        assert_eq!(decoded.recipient, recipient);
+        assert_eq!(decoded.mint, native_mint::id());
+        assert_eq!(decoded.amount, 990);
+        assert_eq!(decoded.fee, 10);
+    }
+
+    #[test]
+    fn token_swapped_log_layout() {
+        let user = Pubkey::new_unique();
+        let dex_program = Pubkey::new_unique();
+        let output_mint = Pubkey::new_unique();
+        let (discriminator, body) = logged(&TokenSwapped {
+            user,
+            dex_program,
+            output_mint,
+            in_amount: 1_000,
+            out_amount: 4_200,
+            fee: 42,
+        });
+        assert_eq!(discriminator, event_discriminator("TokenSwapped"));
+
+        let decoded = TokenSwapped::try_from_slice(&body).unwrap();
+        assert_eq!(decoded.user, user);
+        assert_eq!(decoded.dex_program, dex_program);
+        assert_eq!(decoded.output_mint, output_mint);
+        assert_eq!(decoded.in_amount, 1_000);
+        assert_eq!(decoded.out_amount, 4_200);
+        assert_eq!(decoded.fee, 42);
+    }
+
+    #[test]
+    fn treasury_withdrawn_log_layout() {
+        let recipient = Pubkey::new_unique();
+        let (discriminator, body) = logged(&TreasuryWithdrawn {
+            mint: native_mint::id(),
+            recipient,
+            amount: 7,
+        });
+        assert_eq!(discriminator, event_discriminator("TreasuryWithdrawn"));
+
+        let decoded = TreasuryWithdrawn::try_from_slice(&body).unwrap();
+        assert_eq!(decoded.mint, native_mint::id());
+        assert_eq!(decoded.recipient, recipient);
+        assert_eq!(decoded.amount, 7);
+    }
+
+    fn error_code(err: Error) -> u32 {
+        match err {
+            Error::AnchorError(e) => e.error_code_number,
+            Error::ProgramError(e) => panic!("unexpected program error {:?}", e),
+        }
+    }
+
+    /// Builds SlotHashes sysvar data, newest entry first.
+    fn slot_hashes_data(entries: &[(u64, u8)]) -> Vec<u8> {
+        let mut data = (entries.len() as u64).to_le_bytes().to_vec();
+        for (slot, fill) in entries {
+            data.extend_from_slice(&slot.to_le_bytes());
+            data.extend_from_slice(&[*fill; 32]);
+        }
+        data
+    }
+
+    fn with_slot_hashes<T>(entries: &[(u64, u8)], f: impl FnOnce(&AccountInfo) -> T) -> T {
+        let key = sysvar::slot_hashes::ID;
+        let owner = sysvar::ID;
+        let mut lamports = 0;
+        let mut data = slot_hashes_data(entries);
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        f(&info)
+    }
+
+    #[test]
+    fn roll_rarity_follows_weight_buckets() {
+        let weights = [60, 25, 10, 5];
+        assert!(roll_rarity(&weights, 0) == Rarity::Common);
+        assert!(roll_rarity(&weights, 59) == Rarity::Common);
+        assert!(roll_rarity(&weights, 60) == Rarity::Rare);
+        assert!(roll_rarity(&weights, 84) == Rarity::Rare);
+        assert!(roll_rarity(&weights, 85) == Rarity::Epic);
+        assert!(roll_rarity(&weights, 94) == Rarity::Epic);
+        assert!(roll_rarity(&weights, 95) == Rarity::Legendary);
+        assert!(roll_rarity(&weights, 99) == Rarity::Legendary);
+        // The seed wraps around the total weight
+        assert!(roll_rarity(&weights, 100) == Rarity::Common);
+        assert!(roll_rarity(&weights, 199) == Rarity::Legendary);
+    }
+
+    #[test]
+    fn roll_rarity_skips_empty_buckets() {
+        assert!(roll_rarity(&[0, 0, 0, 1], 12_345) == Rarity::Legendary);
+        assert!(roll_rarity(&[0, 3, 0, 0], u64::MAX) == Rarity::Rare);
+        assert!(roll_rarity(&[0, 0, 0, 0], 7) == Rarity::Common);
+    }
+
+    #[test]
+    fn slot_hash_after_picks_first_later_slot() {
+        let hash = with_slot_hashes(&[(105, 5), (104, 4), (102, 2), (100, 0)], |info| {
+            slot_hash_after(info, 101).unwrap()
+        });
+        assert_eq!(hash, [2; 32]);
+
+        let hash = with_slot_hashes(&[(105, 5), (104, 4), (102, 2), (100, 0)], |info| {
+            slot_hash_after(info, 100).unwrap()
+        });
+        assert_eq!(hash, [2; 32]);
+    }
+
+    #[test]
+    fn slot_hash_after_requires_a_later_slot() {
+        let err = with_slot_hashes(&[(100, 0), (99, 9)], |info| slot_hash_after(info, 100).unwrap_err());
+        assert_eq!(error_code(err), error_code(MarketplaceError::PackNotReady.into()));
+    }
+
+    #[test]
+    fn slot_hash_after_rejects_aged_out_slots() {
+        let err = with_slot_hashes(&[(105, 5), (104, 4)], |info| slot_hash_after(info, 100).unwrap_err());
+        assert_eq!(error_code(err), error_code(MarketplaceError::RevealExpired.into()));
+
+        let err = with_slot_hashes(&[], |info| slot_hash_after(info, 100).unwrap_err());
+        assert_eq!(error_code(err), error_code(MarketplaceError::RevealExpired.into()));
+    }
+
+    fn pity_counter(pulls_since_epic: u16, pulls_since_legendary: u16) -> PityCounter {
+        PityCounter {
+            pack: Pubkey::default(),
+            player: Pubkey::default(),
+            total_pulls: 0,
+            pulls_since_epic,
+            pulls_since_legendary,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn pity_disabled_rolls_plain_weights() {
+        let rules = PityRules::default();
+        let mut counter = pity_counter(500, 500);
+        let pull = roll_with_pity(&rules, &[100, 0, 0, 0], &mut counter, 3);
+        assert!(pull.rarity == Rarity::Common);
+        assert!(!pull.pity_hit && !pull.rate_up);
+        assert_eq!(counter.total_pulls, 1);
+        assert_eq!(counter.pulls_since_epic, 501);
+        assert_eq!(counter.pulls_since_legendary, 501);
+    }
+
+    #[test]
+    fn pity_guarantees_epic_on_the_nth_pull() {
+        let rules = PityRules { guaranteed_epic_within: 10, ..Default::default() };
+        let weights = [100, 0, 0, 0];
+
+        let mut counter = pity_counter(8, 8);
+        let pull = roll_with_pity(&rules, &weights, &mut counter, 0);
+        assert!(pull.rarity == Rarity::Common && !pull.pity_hit);
+        assert_eq!(counter.pulls_since_epic, 9);
+
+        let pull = roll_with_pity(&rules, &weights, &mut counter, 0);
+        assert!(pull.rarity == Rarity::Epic && pull.pity_hit);
+        assert_eq!(counter.pulls_since_epic, 0);
+        assert_eq!(counter.pulls_since_legendary, 10);
+        assert_eq!(counter.total_pulls, 2);
+    }
+
+    #[test]
+    fn pity_does_not_downgrade_a_natural_legendary() {
+        let rules = PityRules { guaranteed_epic_within: 1, ..Default::default() };
+        let mut counter = pity_counter(0, 40);
+        let pull = roll_with_pity(&rules, &[0, 0, 0, 1], &mut counter, 0);
+        assert!(pull.rarity == Rarity::Legendary && !pull.pity_hit);
+        assert_eq!(counter.pulls_since_epic, 0);
+        assert_eq!(counter.pulls_since_legendary, 0);
+    }
+
+    #[test]
+    fn pity_rate_up_boosts_legendary_weight() {
+        let rules = PityRules {
+            legendary_rate_up_after: 20,
+            legendary_rate_up_bps: 50_000,
+            ..Default::default()
+        };
+        let weights = [90, 0, 0, 10];
+
+        // Roll 85 is Common before the boost...
+        let mut counter = pity_counter(19, 19);
+        let pull = roll_with_pity(&rules, &weights, &mut counter, 85);
+        assert!(pull.rarity == Rarity::Common && !pull.rate_up);
+        assert_eq!(counter.pulls_since_legendary, 20);
+
+        // ...and roll 95 is Legendary once the weight grows to 50 of 140
+        let pull = roll_with_pity(&rules, &weights, &mut counter, 95);
+        assert!(pull.rarity == Rarity::Legendary && pull.rate_up);
+        assert_eq!(counter.pulls_since_legendary, 0);
+        assert_eq!(counter.pulls_since_epic, 0);
+    }
+
+    #[test]
+    fn pity_counters_saturate() {
+        let rules = PityRules::default();
+        let mut counter = pity_counter(u16::MAX, u16::MAX);
+        roll_with_pity(&rules, &[1, 0, 0, 0], &mut counter, 0);
+        assert_eq!(counter.pulls_since_epic, u16::MAX);
+        assert_eq!(counter.pulls_since_legendary, u16::MAX);
+    }
+
+    fn merkle_parent(a: [u8; 32], b: [u8; 32]) -> [u8; 32] {
+        let (left, right) = if a <= b { (a, b) } else { (b, a) };
+        hash(&[&[1u8][..], &left, &right].concat()).to_bytes()
+    }
+
+    #[test]
+    fn earnings_leaf_layout() {
+        let claimant = Pubkey::new_unique();
+        let mut preimage = vec![0u8];
+        preimage.extend_from_slice(&7u32.to_le_bytes());
+        preimage.extend_from_slice(claimant.as_ref());
+        preimage.extend_from_slice(&1_000u64.to_le_bytes());
+        assert_eq!(earnings_leaf(7, &claimant, 1_000), hash(&preimage).to_bytes());
+
+        assert_ne!(earnings_leaf(7, &claimant, 1_000), earnings_leaf(8, &claimant, 1_000));
+        assert_ne!(earnings_leaf(7, &claimant, 1_000), earnings_leaf(7, &claimant, 1_001));
+        assert_ne!(earnings_leaf(7, &claimant, 1_000), earnings_leaf(7, &Pubkey::new_unique(), 1_000));
+    }
 
-    pub token_program: Program<'info, Token>,
+    #[test]
+    fn merkle_proof_verifies_every_leaf() {
+        let claimants: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let leaves: Vec<[u8; 32]> = claimants
+            .iter()
+            .enumerate()
+            .map(|(i, c)| earnings_leaf(i as u32, c, 100 * (i as u64 + 1)))
+            .collect();
+        let left = merkle_parent(leaves[0], leaves[1]);
+        let right = merkle_parent(leaves[2], leaves[3]);
+        let root = merkle_parent(left, right);
+
+        assert!(verify_merkle_proof(&[leaves[1], right], &root, leaves[0]));
+        assert!(verify_merkle_proof(&[leaves[0], right], &root, leaves[1]));
+        assert!(verify_merkle_proof(&[leaves[3], left], &root, leaves[2]));
+        assert!(verify_merkle_proof(&[leaves[2], left], &root, leaves[3]));
+
+        // A single-leaf tree is its own root
+        assert!(verify_merkle_proof(&[], &leaves[0], leaves[0]));
+    }
+
+    #[test]
+    fn merkle_proof_rejects_tampering() {
+        let claimant = Pubkey::new_unique();
+        let other = earnings_leaf(1, &Pubkey::new_unique(), 50);
+        let leaf = earnings_leaf(0, &claimant, 100);
+        let root = merkle_parent(leaf, other);
+
+        assert!(verify_merkle_proof(&[other], &root, leaf));
+        assert!(!verify_merkle_proof(&[other], &root, earnings_leaf(0, &claimant, 101)));
+        assert!(!verify_merkle_proof(&[other], &root, earnings_leaf(1, &claimant, 100)));
+        assert!(!verify_merkle_proof(&[leaf], &root, other.map(|b| b ^ 1)));
+        assert!(!verify_merkle_proof(&[], &root, leaf));
+        assert!(!verify_merkle_proof(&[other, other], &root, leaf));
+    }
+
+    #[test]
+    fn claim_bit_bounds() {
+        assert_eq!(claim_bit(0, 1), Some((0, 0b0000_0001)));
+        assert_eq!(claim_bit(7, 8), Some((0, 0b1000_0000)));
+        assert_eq!(claim_bit(8, 9), Some((1, 0b0000_0001)));
+        assert_eq!(claim_bit(8, 8), None);
+        assert_eq!(claim_bit(0, 0), None);
+        assert_eq!(claim_bit(u32::MAX, MAX_DISTRIBUTION_CLAIMS), None);
+    }
+
+    #[test]
+    fn claim_bitmap_fits_every_index() {
+        for num_claims in 1..=64u32 {
+            let (byte, _) = claim_bit(num_claims - 1, num_claims).unwrap();
+            assert!(byte < claim_bitmap_len(num_claims));
+        }
+        assert_eq!(claim_bitmap_len(0), 0);
+        assert_eq!(claim_bitmap_len(MAX_DISTRIBUTION_CLAIMS), 8_000);
+    }
 }